@@ -1,7 +1,7 @@
 use crate::Result;
 use config::{Config, Environment, File};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tracing::{debug, info};
 
 /// Application configuration
@@ -19,6 +19,151 @@ pub struct AppConfig {
     pub large_file_threshold: u64,
     /// Log level (default: info)
     pub log_level: String,
+    /// Limits that hold back mass deletions for confirmation
+    #[serde(default)]
+    pub deletion_safety: DeletionSafety,
+    /// Where files removed by Dropbox deletions are kept
+    #[serde(default)]
+    pub trash: TrashConfig,
+    /// Dropbox folders this machine does not sync
+    #[serde(default)]
+    pub selective_sync: SelectiveSyncConfig,
+    /// Gitignore-style patterns never synced, on top of `.boxdropignore` files
+    #[serde(default)]
+    pub ignore_patterns: Vec<String>,
+    /// Upload names Dropbox refuses under a reversible escaped name instead of skipping them
+    #[serde(default)]
+    pub escape_unsupported_names: bool,
+    /// How symbolic links inside the sync folder are handled
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+    /// Carry POSIX permissions between machines in Dropbox file properties
+    #[serde(default)]
+    pub permissions: PermissionsConfig,
+    /// How the first sync treats files already present in the sync folder
+    #[serde(default)]
+    pub initial_sync: InitialSyncMode,
+    /// How files changed both locally and on Dropbox are settled
+    #[serde(default)]
+    pub conflicts: ConflictConfig,
+}
+
+/// How files changed both locally and on Dropbox are settled
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConflictConfig {
+    /// Policy for paths no rule matches (default: keep_both)
+    pub policy: ConflictPolicy,
+    /// Per-path policies by gitignore-style pattern, e.g. `*.md`; the first match wins
+    #[serde(default)]
+    pub rules: Vec<ConflictRule>,
+    /// Where versions replaced while settling conflicts are kept
+    #[serde(default)]
+    pub backups: ConflictBackupConfig,
+}
+
+/// Local store for versions replaced while settling conflicts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConflictBackupConfig {
+    /// Backup directory; relative paths are inside the sync folder (default: .conflicts)
+    pub path: PathBuf,
+    /// Days to keep backups (default: 30)
+    pub retention_days: u64,
+    /// Backups kept per file, newest first (default: 10)
+    pub max_per_file: usize,
+    /// Maximum total size of the backups in bytes (default: 1GB)
+    pub max_size: u64,
+}
+
+impl Default for ConflictBackupConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from(".conflicts"),
+            retention_days: 30,
+            max_per_file: 10,
+            max_size: 1024 * 1024 * 1024, // 1GB
+        }
+    }
+}
+
+/// A conflict policy for the Dropbox paths matching `pattern`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictRule {
+    pub pattern: String,
+    pub policy: ConflictPolicy,
+}
+
+/// What to do when both sides of a file changed since the last sync
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Keep both, saving the local edit as a conflicted copy (default)
+    #[default]
+    KeepBoth,
+    /// Keep whichever version has the later modification time
+    NewestWins,
+    /// Upload the local version over the Dropbox one
+    LocalWins,
+    /// Replace the local version with the Dropbox one, keeping the edit as a backup
+    RemoteWins,
+    /// Merge non-overlapping edits to text files line by line, keeping both otherwise
+    Merge,
+    /// Leave both sides untouched until the user decides
+    Ask,
+}
+
+/// How the first sync treats files already present in the sync folder
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InitialSyncMode {
+    /// Download Dropbox files and leave untracked local files alone (default)
+    #[default]
+    Download,
+    /// Take over identical local files by content hash and keep differing ones as conflicted copies
+    Adopt,
+}
+
+/// Opt-in syncing of POSIX attributes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PermissionsConfig {
+    /// Store mode bits on upload and apply them on download (default: false)
+    pub enabled: bool,
+    /// Also carry owner and group names, applied only where they exist locally (default: false)
+    pub owners: bool,
+}
+
+/// How symbolic links inside the sync folder are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    /// Leave links out of the sync (default)
+    #[default]
+    Skip,
+    /// Sync what links point to, unless they leave the sync folder or form a loop
+    Follow,
+    /// Upload each link as a small file holding its target and recreate
+    /// Dropbox links locally
+    Store,
+}
+
+/// Thresholds beyond which a sync cycle holds its deletions for confirmation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeletionSafety {
+    /// Maximum number of files a single cycle may delete (default: 500)
+    pub max_files: usize,
+    /// Maximum share of tracked files a single cycle may delete, in percent (default: 25)
+    pub max_percent: f64,
+}
+
+impl Default for DeletionSafety {
+    fn default() -> Self {
+        Self {
+            max_files: 500,
+            max_percent: 25.0,
+        }
+    }
 }
 
 impl Default for AppConfig {
@@ -32,10 +177,68 @@ impl Default for AppConfig {
             max_files_for_inotify: 20_000,
             large_file_threshold: 100 * 1024 * 1024, // 100MB
             log_level: "info".to_string(),
+            deletion_safety: DeletionSafety::default(),
+            trash: TrashConfig::default(),
+            selective_sync: SelectiveSyncConfig::default(),
+            ignore_patterns: Vec::new(),
+            escape_unsupported_names: false,
+            symlinks: SymlinkPolicy::default(),
+            permissions: PermissionsConfig::default(),
+            initial_sync: InitialSyncMode::default(),
+            conflicts: ConflictConfig::default(),
+        }
+    }
+}
+
+/// Local trash for files removed because they were deleted on Dropbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// Move deleted files to the trash instead of unlinking them (default: true)
+    pub enabled: bool,
+    /// Trash directory; relative paths are inside the sync folder (default: .boxdrop-trash)
+    pub path: PathBuf,
+    /// Days to keep trashed files (default: 30)
+    pub retention_days: u64,
+    /// Maximum total size of the trash in bytes (default: 1GB)
+    pub max_size: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: PathBuf::from(".boxdrop-trash"),
+            retention_days: 30,
+            max_size: 1024 * 1024 * 1024, // 1GB
         }
     }
 }
 
+/// Selective sync settings for this machine
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SelectiveSyncConfig {
+    /// Dropbox folders left out of the local copy, e.g. `/Team/Archive`
+    pub excluded_folders: Vec<String>,
+}
+
+impl AppConfig {
+    /// Resolved location of the local trash directory
+    pub fn trash_dir(&self) -> PathBuf {
+        self.sync_folder.join(&self.trash.path)
+    }
+
+    /// Resolved location of the conflict backup directory
+    pub fn conflicts_dir(&self) -> PathBuf {
+        self.sync_folder.join(&self.conflicts.backups.path)
+    }
+
+    /// Folders the daemon keeps its own files in, which are never synced
+    pub fn stores(&self) -> Vec<PathBuf> {
+        vec![self.trash_dir(), self.conflicts_dir()]
+    }
+}
+
 /// Configuration manager for the application
 pub struct ConfigManager {
     config: AppConfig,
@@ -75,9 +278,9 @@ impl ConfigManager {
     }
     
     /// Load configuration from a specific file
-    fn load_from_file(path: &PathBuf) -> Result<AppConfig> {
+    fn load_from_file(path: &Path) -> Result<AppConfig> {
         let config = Config::builder()
-            .add_source(File::from(path.as_path()))
+            .add_source(File::from(path))
             .add_source(Environment::with_prefix("DROPBOX_SYNC"))
             .build()
             .map_err(|e| anyhow::anyhow!("Failed to build config: {}", e))?;
@@ -87,7 +290,7 @@ impl ConfigManager {
     }
     
     /// Save configuration to file
-    fn save_to_file(path: &PathBuf, config: &AppConfig) -> Result<()> {
+    fn save_to_file(path: &Path, config: &AppConfig) -> Result<()> {
         let json = serde_json::to_string_pretty(config)
             .map_err(|e| anyhow::anyhow!("Failed to serialize config: {}", e))?;
         
@@ -157,7 +360,6 @@ impl std::ops::DerefMut for ConfigManager {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::TempDir;
     
     #[test]
     fn test_default_config() {
@@ -166,6 +368,7 @@ mod tests {
         assert_eq!(config.max_files_for_inotify, 20_000);
         assert_eq!(config.large_file_threshold, 100 * 1024 * 1024);
         assert_eq!(config.log_level, "info");
+        assert_eq!(config.deletion_safety.max_files, 500);
     }
     
     #[test]
@@ -179,4 +382,46 @@ mod tests {
         assert_eq!(config.large_file_threshold, deserialized.large_file_threshold);
         assert_eq!(config.log_level, deserialized.log_level);
     }
+
+    #[test]
+    fn test_missing_sections_use_defaults() {
+        let json = r#"{
+            "dropbox_token": "",
+            "sync_folder": "/tmp/Dropbox",
+            "polling_interval": 300,
+            "max_files_for_inotify": 20000,
+            "large_file_threshold": 104857600,
+            "log_level": "info"
+        }"#;
+        let config: AppConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.deletion_safety.max_percent, 25.0);
+        assert_eq!(config.trash_dir(), PathBuf::from("/tmp/Dropbox/.boxdrop-trash"));
+        assert!(config.selective_sync.excluded_folders.is_empty());
+    }
+
+    #[test]
+    fn test_partial_sections_use_defaults() {
+        let json = r#"{
+            "dropbox_token": "",
+            "sync_folder": "/tmp/Dropbox",
+            "polling_interval": 300,
+            "max_files_for_inotify": 20000,
+            "large_file_threshold": 104857600,
+            "log_level": "info",
+            "deletion_safety": { "max_files": 50 },
+            "trash": { "retention_days": 7 },
+            "permissions": { "owners": true },
+            "conflicts": { "policy": "keep_both", "backups": { "max_per_file": 3 } }
+        }"#;
+        let config: AppConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.deletion_safety.max_files, 50);
+        assert_eq!(config.deletion_safety.max_percent, 25.0);
+        assert_eq!(config.trash.retention_days, 7);
+        assert!(config.trash.enabled);
+        assert_eq!(config.trash_dir(), PathBuf::from("/tmp/Dropbox/.boxdrop-trash"));
+        assert!(config.permissions.owners);
+        assert!(!config.permissions.enabled);
+        assert_eq!(config.conflicts.backups.max_per_file, 3);
+        assert_eq!(config.conflicts.backups.retention_days, 30);
+    }
 } 
\ No newline at end of file
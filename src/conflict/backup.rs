@@ -1,10 +1,431 @@
-// TODO: Implement backup system in future task
+use crate::Result;
+use crate::config::manager::AppConfig;
+use crate::sync::atomic;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+/// Format of the dated directories inside a backup store
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// File at the root of a store describing the stored items
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Limits applied when pruning a backup store
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Remove items stored more than this many days ago
+    pub max_age_days: Option<u64>,
+    /// Remove the oldest items once the store grows beyond this many bytes
+    pub max_total_bytes: Option<u64>,
+    /// Keep only this many of the newest items per original path
+    pub max_per_file: Option<usize>,
+}
+
+/// Which side of a conflict a stored version came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupSource {
+    Local,
+    Dropbox,
+}
+
+/// What the manifest records about a stored item beyond its location
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub source: Option<BackupSource>,
+    /// Dropbox rev of the stored version
+    pub rev: Option<String>,
+    pub content_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ManifestEntry {
+    /// Location inside the store, relative to its root
+    stored: PathBuf,
+    /// Original path relative to the sync folder; the stored name may carry a `~N` suffix
+    #[serde(default)]
+    relative: Option<PathBuf>,
+    stored_at: DateTime<Utc>,
+    #[serde(flatten)]
+    info: BackupInfo,
+}
+
+/// A file kept in a backup store
+#[derive(Debug, Clone)]
+pub struct BackupItem {
+    /// Day the file was stored
+    pub date: NaiveDate,
+    /// Original path relative to the sync folder
+    pub relative: PathBuf,
+    /// Where the file is kept inside the store
+    pub stored: PathBuf,
+    pub size: u64,
+    /// When the item was stored, if the manifest knows
+    pub stored_at: Option<DateTime<Utc>>,
+    pub info: BackupInfo,
+}
+
+/// Store that preserves files in dated directories instead of discarding them
+///
+/// Files are kept as `<root>/<YYYY-MM-DD>/<original relative path>`, so the
+/// original folder structure stays recognisable and restoring is a rename.
+/// A manifest at the root records the original path of each item and when
+/// and from where it was stored.
 pub struct Backup {
-    // TODO: Add backup implementation
+    root: PathBuf,
+    retention: RetentionPolicy,
 }
 
 impl Backup {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(root: PathBuf, retention: RetentionPolicy) -> Self {
+        Self { root, retention }
+    }
+
+    /// Store backing the local trash configured in `AppConfig::trash`
+    pub fn trash(config: &AppConfig) -> Self {
+        Self::new(config.trash_dir(), RetentionPolicy {
+            max_age_days: Some(config.trash.retention_days),
+            max_total_bytes: Some(config.trash.max_size),
+            max_per_file: None,
+        })
+    }
+
+    /// Store for versions replaced while settling conflicts, `AppConfig::conflicts.backups`
+    pub fn conflicts(config: &AppConfig) -> Self {
+        let backups = &config.conflicts.backups;
+        Self::new(config.conflicts_dir(), RetentionPolicy {
+            max_age_days: Some(backups.retention_days),
+            max_total_bytes: Some(backups.max_size),
+            max_per_file: Some(backups.max_per_file),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Move `source` into today's directory under its relative path
+    ///
+    /// An existing item with the same name gets a numbered suffix rather
+    /// than being overwritten. Returns where the file was stored.
+    pub fn preserve(&self, source: &Path, relative: &Path) -> Result<PathBuf> {
+        self.preserve_with(source, relative, BackupInfo::default())
+    }
+
+    /// Like [`Backup::preserve`], recording `info` in the manifest
+    pub fn preserve_with(&self, source: &Path, relative: &Path, info: BackupInfo) -> Result<PathBuf> {
+        let target = self.target(relative)?;
+        move_file(source, &target)?;
+        self.record(&target, relative, info);
+        debug!("Preserved {} as {}", source.display(), target.display());
+        Ok(target)
+    }
+
+    /// Store `content` as a version of `relative`, such as a Dropbox version about to be replaced
+    pub fn store(&self, content: &[u8], relative: &Path, info: BackupInfo) -> Result<PathBuf> {
+        let target = self.target(relative)?;
+        std::fs::write(&target, content)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", target.display(), e))?;
+        self.record(&target, relative, info);
+        debug!("Stored {} bytes of {} as {}", content.len(), relative.display(), target.display());
+        Ok(target)
+    }
+
+    /// Free location for a new item in today's directory, with its parents created
+    fn target(&self, relative: &Path) -> Result<PathBuf> {
+        let day = self.root.join(Local::now().format(DATE_FORMAT).to_string());
+        let target = unique_path(&day.join(relative));
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        Ok(target)
+    }
+
+    fn manifest(&self) -> Vec<ManifestEntry> {
+        std::fs::read_to_string(self.root.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    /// Rewrite the manifest; failures only cost metadata, so they are logged
+    fn write_manifest(&self, entries: &[ManifestEntry]) {
+        let path = self.root.join(MANIFEST_FILE);
+        let written = serde_json::to_string_pretty(entries)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize backup manifest: {}", e))
+            .and_then(|json| atomic::write_file(&path, json.as_bytes()));
+        if let Err(e) = written {
+            warn!("Failed to write {}: {}", path.display(), e);
+        }
+    }
+
+    fn record(&self, stored: &Path, original: &Path, info: BackupInfo) {
+        let Ok(relative) = stored.strip_prefix(&self.root) else { return };
+        let mut entries = self.manifest();
+        entries.push(ManifestEntry {
+            stored: relative.to_path_buf(),
+            relative: Some(original.to_path_buf()),
+            stored_at: Utc::now(),
+            info,
+        });
+        self.write_manifest(&entries);
+    }
+
+    /// Drop manifest entries of items that are no longer stored
+    fn forget(&self, removed: &[&Path]) {
+        let mut entries = self.manifest();
+        let before = entries.len();
+        entries.retain(|entry| !removed.contains(&self.root.join(&entry.stored).as_path()));
+        if entries.len() != before {
+            self.write_manifest(&entries);
+        }
+    }
+
+    /// List every stored item, oldest first
+    pub fn list(&self) -> Result<Vec<BackupItem>> {
+        let mut items = Vec::new();
+        if !self.root.exists() {
+            return Ok(items);
+        }
+        let manifest: HashMap<PathBuf, ManifestEntry> = self.manifest()
+            .into_iter()
+            .map(|entry| (self.root.join(&entry.stored), entry))
+            .collect();
+
+        let days = std::fs::read_dir(&self.root)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", self.root.display(), e))?;
+        for day in days.filter_map(|d| d.ok()) {
+            let Some(date) = day.file_name().to_str()
+                .and_then(|name| NaiveDate::parse_from_str(name, DATE_FORMAT).ok())
+            else {
+                continue;
+            };
+
+            for entry in WalkDir::new(day.path()).into_iter().filter_map(|e| e.ok()) {
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let Ok(relative) = entry.path().strip_prefix(day.path()) else { continue };
+                let recorded = manifest.get(entry.path());
+                items.push(BackupItem {
+                    date,
+                    relative: recorded.and_then(|r| r.relative.clone()).unwrap_or_else(|| relative.to_path_buf()),
+                    stored: entry.path().to_path_buf(),
+                    size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                    stored_at: recorded.map(|r| r.stored_at),
+                    info: recorded.map(|r| r.info.clone()).unwrap_or_default(),
+                });
+            }
+        }
+
+        items.sort_by(|a, b| {
+            a.date.cmp(&b.date)
+                .then_with(|| a.stored_at.cmp(&b.stored_at))
+                .then_with(|| a.relative.cmp(&b.relative))
+                .then_with(|| a.stored.cmp(&b.stored))
+        });
+        Ok(items)
     }
-} 
\ No newline at end of file
+
+    /// Move the newest stored copy of `relative` (or the one from `date`) back to `destination`
+    pub fn restore(&self, relative: &Path, date: Option<NaiveDate>, destination: &Path) -> Result<PathBuf> {
+        let item = self.list()?
+            .into_iter()
+            .rfind(|item| item.relative == relative && date.is_none_or(|d| item.date == d))
+            .ok_or_else(|| anyhow::anyhow!("No stored copy of {}", relative.display()))?;
+
+        if destination.exists() {
+            return Err(anyhow::anyhow!("{} already exists; move it away first", destination.display()));
+        }
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", parent.display(), e))?;
+        }
+
+        move_file(&item.stored, destination)?;
+        remove_empty_parents(&item.stored, &self.root);
+        self.forget(&[&item.stored]);
+        info!("Restored {} from {}", destination.display(), item.date);
+        Ok(destination.to_path_buf())
+    }
+
+    /// Apply the retention policy, returning how many items were removed
+    pub fn prune(&self) -> Result<usize> {
+        self.prune_except(&HashSet::new())
+    }
+
+    /// Apply the retention policy, never removing the `kept` stored files
+    ///
+    /// Kept files do not count towards the per-file and size limits.
+    pub fn prune_except(&self, kept: &HashSet<PathBuf>) -> Result<usize> {
+        let mut items = self.list()?;
+        items.retain(|item| !kept.contains(&item.stored));
+        let mut expired = Vec::new();
+
+        if let Some(days) = self.retention.max_age_days {
+            let cutoff = Local::now().date_naive() - chrono::Duration::days(days as i64);
+            let (old, kept): (Vec<_>, Vec<_>) = items.into_iter().partition(|item| item.date < cutoff);
+            expired.extend(old);
+            items = kept;
+        }
+
+        if let Some(keep) = self.retention.max_per_file {
+            let mut seen: HashMap<PathBuf, usize> = HashMap::new();
+            let mut kept = Vec::with_capacity(items.len());
+            for item in items.into_iter().rev() {
+                let count = seen.entry(item.relative.clone()).or_default();
+                *count += 1;
+                if *count > keep { expired.push(item) } else { kept.push(item) }
+            }
+            kept.reverse();
+            items = kept;
+        }
+
+        if let Some(limit) = self.retention.max_total_bytes {
+            let mut total: u64 = items.iter().map(|item| item.size).sum();
+            let mut oldest_first = items.into_iter();
+            while total > limit {
+                let Some(item) = oldest_first.next() else { break };
+                total -= item.size;
+                expired.push(item);
+            }
+        }
+
+        for item in &expired {
+            std::fs::remove_file(&item.stored)
+                .map_err(|e| anyhow::anyhow!("Failed to remove {}: {}", item.stored.display(), e))?;
+            remove_empty_parents(&item.stored, &self.root);
+        }
+        if !expired.is_empty() {
+            self.forget(&expired.iter().map(|item| item.stored.as_path()).collect::<Vec<_>>());
+            info!("Pruned {} items from {}", expired.len(), self.root.display());
+        }
+        Ok(expired.len())
+    }
+}
+
+/// Rename a file, falling back to copy and delete across file systems
+fn move_file(source: &Path, target: &Path) -> Result<()> {
+    if std::fs::rename(source, target).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(source, target)
+        .map_err(|e| anyhow::anyhow!("Failed to copy {} to {}: {}", source.display(), target.display(), e))?;
+    std::fs::remove_file(source)
+        .map_err(|e| anyhow::anyhow!("Failed to remove {}: {}", source.display(), e))
+}
+
+/// First of `path`, `path~1`, `path~2`, ... that does not exist yet
+fn unique_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{}~{}", name, n)))
+        .find(|candidate| !candidate.exists())
+        .expect("unbounded suffix search")
+}
+
+/// Remove now-empty directories between `path` and `root`
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut current = path.parent();
+    while let Some(dir) = current {
+        if dir == root || !dir.starts_with(root) || std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+        current = dir.parent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_preserve_list_and_restore() {
+        let dir = TempDir::new().unwrap();
+        let store = Backup::new(dir.path().join("trash"), RetentionPolicy::default());
+        let source = dir.path().join("report.txt");
+
+        std::fs::write(&source, b"first").unwrap();
+        store.preserve(&source, Path::new("Docs/report.txt")).unwrap();
+        std::fs::write(&source, b"second").unwrap();
+        store.preserve(&source, Path::new("Docs/report.txt")).unwrap();
+        assert!(!source.exists());
+
+        let items = store.list().unwrap();
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().all(|item| item.relative == Path::new("Docs/report.txt")));
+
+        let restored = dir.path().join("restored.txt");
+        store.restore(Path::new("Docs/report.txt"), None, &restored).unwrap();
+        assert_eq!(store.list().unwrap().len(), 1);
+
+        // A name that looks like a numbered duplicate keeps its own identity
+        std::fs::write(&source, b"tilde").unwrap();
+        store.preserve(&source, Path::new("Docs/report.txt~1")).unwrap();
+        let items = store.list().unwrap();
+        assert!(items.iter().any(|item| item.relative == Path::new("Docs/report.txt~1")));
+        let restored = dir.path().join("tilde.txt");
+        store.restore(Path::new("Docs/report.txt~1"), None, &restored).unwrap();
+        assert_eq!(std::fs::read(&restored).unwrap(), b"tilde");
+        assert_eq!(store.list().unwrap()[0].relative, Path::new("Docs/report.txt"));
+    }
+
+    #[test]
+    fn test_prune_by_age_and_size() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("trash");
+        let old = root.join("2000-01-01").join("old.bin");
+        std::fs::create_dir_all(old.parent().unwrap()).unwrap();
+        std::fs::write(&old, vec![0u8; 10]).unwrap();
+
+        let store = Backup::new(root.clone(), RetentionPolicy { max_age_days: Some(30), max_total_bytes: Some(15), max_per_file: None });
+        let fresh = dir.path().join("fresh.bin");
+        std::fs::write(&fresh, vec![0u8; 10]).unwrap();
+        store.preserve(&fresh, Path::new("a.bin")).unwrap();
+        std::fs::write(&fresh, vec![0u8; 10]).unwrap();
+        store.preserve(&fresh, Path::new("b.bin")).unwrap();
+
+        assert_eq!(store.prune().unwrap(), 2);
+        let remaining = store.list().unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].relative, Path::new("b.bin"));
+        assert!(!root.join("2000-01-01").exists());
+    }
+
+    #[test]
+    fn test_manifest_and_count_per_file() {
+        let dir = TempDir::new().unwrap();
+        let store = Backup::new(dir.path().join(".conflicts"), RetentionPolicy { max_per_file: Some(2), ..RetentionPolicy::default() });
+        let info = BackupInfo { source: Some(BackupSource::Dropbox), rev: Some("a1".to_string()), content_hash: None };
+        for version in [b"one", b"two", b"six"] {
+            store.store(version, Path::new("Notes/todo.md"), info.clone()).unwrap();
+        }
+        store.store(b"other", Path::new("other.md"), BackupInfo::default()).unwrap();
+
+        let items = store.list().unwrap();
+        assert_eq!(items.len(), 4);
+        assert!(items.iter().filter(|item| item.relative == Path::new("Notes/todo.md")).all(|item| item.info == info));
+
+        assert_eq!(store.prune().unwrap(), 1);
+        let kept: Vec<_> = store.list().unwrap().into_iter()
+            .filter(|item| item.relative == Path::new("Notes/todo.md"))
+            .map(|item| std::fs::read(&item.stored).unwrap())
+            .collect();
+        assert_eq!(kept, vec![b"two".to_vec(), b"six".to_vec()]);
+        assert_eq!(store.manifest().len(), 3);
+
+        let queued = store.store(b"ten", Path::new("Notes/todo.md"), info.clone()).unwrap();
+        store.store(b"new", Path::new("Notes/todo.md"), info.clone()).unwrap();
+        assert_eq!(store.prune_except(&HashSet::from([queued.clone()])).unwrap(), 1);
+        assert!(queued.exists());
+    }
+}
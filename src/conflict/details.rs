@@ -0,0 +1,159 @@
+use crate::dropbox::content_hash::content_hash;
+use crate::utils::timestamps;
+use super::merge;
+use super::resolver::{self, Conflict};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use similar::TextDiff;
+use std::fmt::Write as _;
+
+/// Largest file shown as a text diff (1 MiB)
+pub const MAX_DIFF_SIZE: usize = 1024 * 1024;
+
+/// One side of a conflict
+#[derive(Debug, Clone, Serialize)]
+pub struct Version {
+    pub size: u64,
+    pub client_modified: Option<DateTime<Utc>>,
+    pub server_modified: Option<DateTime<Utc>>,
+    /// Dropbox content hash, when the content is at hand
+    pub content_hash: Option<String>,
+    /// Dropbox rev; the local edit has none until it is uploaded
+    pub rev: Option<String>,
+    /// Machine the version was written on; Dropbox does not report one
+    pub device: Option<String>,
+}
+
+/// Both sides of a conflict side by side, for review before deciding
+#[derive(Debug, Clone, Serialize)]
+pub struct ConflictDetails {
+    pub remote: String,
+    /// Rev both sides last agreed on
+    pub base_rev: Option<String>,
+    pub local: Version,
+    /// The Dropbox version, or `None` if the file was deleted there
+    pub dropbox: Option<Version>,
+    /// Unified diff from the Dropbox version to the local one, for text files
+    /// up to [`MAX_DIFF_SIZE`]
+    pub diff: Option<String>,
+}
+
+impl ConflictDetails {
+    /// Describe a conflict, using the content of each side when available
+    pub fn new(conflict: &Conflict, local: Option<&[u8]>, dropbox: Option<&[u8]>) -> Self {
+        let local_version = Version {
+            size: local.map_or(conflict.local_size, |content| content.len() as u64),
+            client_modified: conflict.local_modified,
+            server_modified: None,
+            content_hash: local.map(content_hash),
+            rev: None,
+            device: Some(resolver::host_name()),
+        };
+        let dropbox_version = conflict.remote_metadata.as_ref().map(|metadata| Version {
+            size: metadata.size,
+            client_modified: metadata.client_modified.as_deref().and_then(timestamps::parse_dropbox_time),
+            server_modified: metadata.server_modified.as_deref().and_then(timestamps::parse_dropbox_time),
+            content_hash: metadata.content_hash.clone().or_else(|| dropbox.map(content_hash)),
+            rev: Some(metadata.rev.clone()),
+            device: None,
+        });
+
+        let diff = match (dropbox, local) {
+            (Some(dropbox), Some(local)) => text_diff(&conflict.remote, dropbox, local),
+            _ => None,
+        };
+
+        Self {
+            remote: conflict.remote.clone(),
+            base_rev: conflict.base_rev.clone(),
+            local: local_version,
+            dropbox: dropbox_version,
+            diff,
+        }
+    }
+
+    /// The metadata of both sides as a plain text table
+    pub fn table(&self) -> String {
+        let time = |value: Option<DateTime<Utc>>| value
+            .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string());
+        let hash = |value: &Option<String>| value.as_ref().map(|hash| hash.chars().take(16).collect::<String>());
+
+        let fields: Vec<(&str, Option<String>, Option<String>)> = vec![
+            ("Size", Some(self.local.size.to_string()), self.dropbox.as_ref().map(|v| v.size.to_string())),
+            ("Modified", time(self.local.client_modified), self.dropbox.as_ref().and_then(|v| time(v.client_modified))),
+            ("Server modified", time(self.local.server_modified), self.dropbox.as_ref().and_then(|v| time(v.server_modified))),
+            ("Content hash", hash(&self.local.content_hash), self.dropbox.as_ref().and_then(|v| hash(&v.content_hash))),
+            ("Rev", self.local.rev.clone(), self.dropbox.as_ref().and_then(|v| v.rev.clone())),
+            ("Device", self.local.device.clone(), self.dropbox.as_ref().and_then(|v| v.device.clone())),
+        ];
+
+        let dropbox_heading = if self.dropbox.is_some() { "Dropbox" } else { "Dropbox (deleted)" };
+        let mut table = format!("{:<16}{:<24}{}\n", "", "Local", dropbox_heading);
+        for (label, local, dropbox) in fields {
+            let _ = writeln!(table, "{:<16}{:<24}{}", label,
+                             local.unwrap_or_else(|| "-".to_string()),
+                             dropbox.unwrap_or_else(|| "-".to_string()));
+        }
+        table
+    }
+}
+
+/// Unified diff between two text versions, or `None` for binary or large files
+fn text_diff(remote: &str, dropbox: &[u8], local: &[u8]) -> Option<String> {
+    if dropbox.len() > MAX_DIFF_SIZE || local.len() > MAX_DIFF_SIZE {
+        return None;
+    }
+    if !merge::is_text(dropbox) || !merge::is_text(local) {
+        return None;
+    }
+    let (dropbox, local) = (String::from_utf8_lossy(dropbox), String::from_utf8_lossy(local));
+    let diff = TextDiff::from_lines(dropbox.as_ref(), local.as_ref())
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("{} (Dropbox)", remote), &format!("{} (local)", remote))
+        .to_string();
+    Some(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dropbox::client::FileMetadata;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_details_compare_both_sides() {
+        let conflict = Conflict {
+            local: PathBuf::from("/tmp/Dropbox/notes.md"),
+            remote: "/notes.md".to_string(),
+            base_rev: Some("015a".to_string()),
+            local_size: 0,
+            local_modified: timestamps::parse_dropbox_time("2024-06-01T10:00:00Z"),
+            remote_metadata: Some(FileMetadata {
+                name: "notes.md".to_string(),
+                path_lower: "/notes.md".to_string(),
+                path_display: "/notes.md".to_string(),
+                id: "id:1".to_string(),
+                client_modified: Some("2024-06-01T09:00:00Z".to_string()),
+                server_modified: Some("2024-06-01T09:00:05Z".to_string()),
+                rev: "015b".to_string(),
+                size: 12,
+                is_downloadable: true,
+                content_hash: None,
+                symlink_info: None,
+                tag: "file".to_string(),
+            }),
+        };
+
+        let details = ConflictDetails::new(&conflict, Some(b"one\nthree\n"), Some(b"one\ntwo\n"));
+        assert_eq!(details.local.size, 10);
+        assert_eq!(details.dropbox.as_ref().unwrap().rev.as_deref(), Some("015b"));
+        assert_eq!(details.dropbox.as_ref().unwrap().content_hash, Some(content_hash(b"one\ntwo\n")));
+        let diff = details.diff.as_deref().unwrap();
+        assert!(diff.contains("-two\n") && diff.contains("+three\n"));
+        assert!(details.table().contains("015b"));
+
+        let binary = ConflictDetails::new(&conflict, Some(b"\0\x01"), Some(b"one\n"));
+        assert!(binary.diff.is_none());
+    }
+}
@@ -0,0 +1,89 @@
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+use std::ops::Range;
+
+/// Bytes inspected when deciding whether content is text
+const SNIFF_LEN: usize = 8000;
+
+/// Whether content looks like text: valid UTF-8 without NUL bytes near the start
+pub fn is_text(content: &[u8]) -> bool {
+    !content[..content.len().min(SNIFF_LEN)].contains(&0) && std::str::from_utf8(content).is_ok()
+}
+
+/// A change one side made to a range of base lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Hunk<'a> {
+    base: Range<usize>,
+    lines: Vec<&'a str>,
+}
+
+/// Merge two edited versions of `base` line by line
+///
+/// Returns `None` when both sides changed the same or adjacent lines
+/// differently, since there is no safe way to combine those edits.
+pub fn three_way(base: &str, ours: &str, theirs: &str) -> Option<String> {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let mut hunks = hunks_of(&base_lines, ours);
+    hunks.extend(hunks_of(&base_lines, theirs));
+    hunks.sort_by_key(|hunk| (hunk.base.start, hunk.base.end));
+    hunks.dedup();
+
+    for pair in hunks.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        if b.base.start <= a.base.end {
+            return None;
+        }
+    }
+
+    let mut merged = String::with_capacity(ours.len().max(theirs.len()));
+    let mut position = 0;
+    for hunk in &hunks {
+        base_lines[position..hunk.base.start].iter().for_each(|line| merged.push_str(line));
+        hunk.lines.iter().for_each(|line| merged.push_str(line));
+        position = hunk.base.end;
+    }
+    base_lines[position..].iter().for_each(|line| merged.push_str(line));
+    Some(merged)
+}
+
+/// Changes turning `base` into `edited`, in base order
+fn hunks_of<'a>(base: &[&str], edited: &'a str) -> Vec<Hunk<'a>> {
+    let edited: Vec<&str> = edited.split_inclusive('\n').collect();
+    capture_diff_slices(Algorithm::Myers, base, &edited)
+        .into_iter()
+        .filter_map(|op| match op {
+            DiffOp::Equal { .. } => None,
+            DiffOp::Delete { old_index, old_len, .. } => Some(Hunk { base: old_index..old_index + old_len, lines: Vec::new() }),
+            DiffOp::Insert { old_index, new_index, new_len } => Some(Hunk {
+                base: old_index..old_index,
+                lines: edited[new_index..new_index + new_len].to_vec(),
+            }),
+            DiffOp::Replace { old_index, old_len, new_index, new_len } => Some(Hunk {
+                base: old_index..old_index + old_len,
+                lines: edited[new_index..new_index + new_len].to_vec(),
+            }),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merges_separate_edits_and_refuses_overlaps() {
+        let base = "title\n\none\ntwo\nthree\nfour\nfive\n";
+        let ours = "Title\n\none\ntwo\nthree\nfour\nfive\n";
+        let theirs = "title\n\none\ntwo\nthree\nfour\nfive\nsix\n";
+        assert_eq!(three_way(base, ours, theirs).unwrap(), "Title\n\none\ntwo\nthree\nfour\nfive\nsix\n");
+
+        let same = "title\n\none\n2\nthree\nfour\nfive\n";
+        assert_eq!(three_way(base, same, same).unwrap(), same);
+
+        let theirs = "title\n\none\nzwei\nthree\nfour\nfive\n";
+        assert_eq!(three_way(base, same, theirs), None);
+
+        assert!(is_text(b"plain\ntext\n"));
+        assert!(!is_text(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert!(!is_text(&[0xff, 0xfe, b'a']));
+    }
+}
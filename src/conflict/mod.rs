@@ -1,2 +1,5 @@
 pub mod resolver;
-pub mod backup; 
\ No newline at end of file
+pub mod backup;
+pub mod queue;
+pub mod merge;
+pub mod details;
\ No newline at end of file
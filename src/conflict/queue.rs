@@ -0,0 +1,287 @@
+use crate::{ConfigManager, Result};
+use crate::config::manager::ConflictPolicy;
+use crate::sync::{atomic, paths};
+use super::resolver::{self, Conflict};
+use chrono::{DateTime, Utc};
+use ignore::gitignore::Gitignore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// A conflict waiting for the user to decide
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedConflict {
+    pub detected_at: DateTime<Utc>,
+    pub conflict: Conflict,
+    /// Copies of both versions taken when the conflict was detected
+    pub backups: Vec<PathBuf>,
+    /// Policy chosen by the user, applied on the daemon's next sync cycle
+    pub decision: Option<ConflictPolicy>,
+}
+
+/// Conflicts awaiting a decision, shared between the daemon and the CLI
+///
+/// Paths in the queue are left out of syncing until their conflict is settled.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConflictQueue {
+    pub conflicts: Vec<QueuedConflict>,
+}
+
+impl ConflictQueue {
+    /// Location of the conflict queue inside the data directory
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(ConfigManager::data_dir()?.join("conflicts.json"))
+    }
+
+    /// Load the queue, returning an empty one if nothing is waiting
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    /// Save the queue, removing the file once it is empty
+    ///
+    /// The file is replaced in one step, so the daemon never sees part of a
+    /// batch of decisions.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if self.conflicts.is_empty() {
+            if path.exists() {
+                std::fs::remove_file(path)
+                    .map_err(|e| anyhow::anyhow!("Failed to remove {}: {}", path.display(), e))?;
+            }
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize conflict queue: {}", e))?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", tmp.display(), e))?;
+        std::fs::rename(&tmp, path)
+            .map_err(|e| anyhow::anyhow!("Failed to replace {}: {}", path.display(), e))
+    }
+
+    /// Queue a conflict, replacing an earlier one on the same path
+    pub fn push(&mut self, conflict: Conflict, backups: Vec<PathBuf>) {
+        self.remove(&conflict.remote);
+        self.conflicts.push(QueuedConflict { detected_at: Utc::now(), conflict, backups, decision: None });
+    }
+
+    /// The queued conflict on a Dropbox path
+    pub fn get(&self, remote: &str) -> Option<&QueuedConflict> {
+        let key = paths::index_key(remote);
+        self.conflicts.iter().find(|queued| paths::index_key(&queued.conflict.remote) == key)
+    }
+
+    /// Take a conflict off the queue
+    pub fn remove(&mut self, remote: &str) -> Option<QueuedConflict> {
+        let key = paths::index_key(remote);
+        let position = self.conflicts.iter().position(|queued| paths::index_key(&queued.conflict.remote) == key)?;
+        Some(self.conflicts.remove(position))
+    }
+
+    /// Record the user's choice for a conflict, returning whether it was queued
+    pub fn decide(&mut self, remote: &str, policy: ConflictPolicy) -> bool {
+        let key = paths::index_key(remote);
+        match self.conflicts.iter_mut().find(|queued| paths::index_key(&queued.conflict.remote) == key) {
+            Some(queued) => {
+                queued.decision = Some(policy);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Close out a settled decision: drop the conflict, or clear its decision
+    /// if settling failed
+    ///
+    /// An entry detected at another time was queued afresh while settling and
+    /// is left for the user.
+    pub fn finish(&mut self, remote: &str, detected_at: DateTime<Utc>, settled: bool) {
+        let key = paths::index_key(remote);
+        let Some(position) = self.conflicts.iter()
+            .position(|queued| paths::index_key(&queued.conflict.remote) == key && queued.detected_at == detected_at)
+        else {
+            return;
+        };
+        if settled {
+            self.conflicts.remove(position);
+        } else {
+            self.conflicts[position].decision = None;
+        }
+    }
+
+    /// Queued conflicts picked by a filter
+    pub fn select<'a>(&'a self, filter: &'a ConflictFilter) -> impl Iterator<Item = &'a QueuedConflict> + 'a {
+        self.conflicts.iter().filter(|queued| filter.matches(&queued.conflict.remote))
+    }
+
+    /// Record one choice for every conflict picked by a filter, returning their paths
+    pub fn decide_matching(&mut self, filter: &ConflictFilter, policy: ConflictPolicy) -> Vec<String> {
+        self.conflicts.iter_mut()
+            .filter(|queued| filter.matches(&queued.conflict.remote))
+            .map(|queued| {
+                queued.decision = Some(policy);
+                queued.conflict.remote.clone()
+            })
+            .collect()
+    }
+
+    /// Whether syncing `remote` would touch a queued conflict, at it or below it
+    pub fn blocks(&self, remote: &str) -> bool {
+        let key = paths::index_key(remote);
+        self.conflicts.iter().any(|queued| paths::is_within(&paths::index_key(&queued.conflict.remote), &key))
+    }
+
+    /// Backups of the queued conflicts, which must outlive the retention policy
+    pub fn backups(&self) -> HashSet<PathBuf> {
+        self.conflicts.iter().flat_map(|queued| queued.backups.iter().cloned()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Picks queued conflicts by folder and gitignore-style pattern; an empty filter picks all
+#[derive(Default)]
+pub struct ConflictFilter {
+    folder: Option<String>,
+    pattern: Option<Gitignore>,
+}
+
+impl ConflictFilter {
+    pub fn new(folder: Option<&str>, pattern: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            folder: folder.map(|folder| paths::index_key(folder.trim_end_matches('/'))),
+            pattern: pattern.map(resolver::pattern_matcher).transpose()?,
+        })
+    }
+
+    /// Whether a Dropbox path is at or below the folder and matches the pattern
+    pub fn matches(&self, remote: &str) -> bool {
+        self.folder.as_ref().is_none_or(|folder| paths::is_within(&paths::index_key(remote), folder))
+            && self.pattern.as_ref().is_none_or(|matcher| resolver::matches(matcher, remote))
+    }
+}
+
+/// Most outcomes kept in the conflict report
+const REPORT_LIMIT: usize = 1000;
+
+/// What happened to a decided conflict when the daemon settled it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettledConflict {
+    pub settled_at: DateTime<Utc>,
+    pub remote: String,
+    pub decision: ConflictPolicy,
+    /// How the decision played out, e.g. `KeepRemote` for a newest-wins choice
+    pub resolution: String,
+    /// Why settling failed; the conflict stays queued without a decision
+    pub error: Option<String>,
+}
+
+/// Outcomes of settled conflicts, newest last, written by the daemon for the CLI
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConflictReport {
+    pub settled: Vec<SettledConflict>,
+}
+
+impl ConflictReport {
+    /// Location of the report inside the data directory
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(ConfigManager::data_dir()?.join("conflict_report.json"))
+    }
+
+    /// Load the report, returning an empty one if nothing was settled yet
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    /// Save the report, replacing the file in one step so the CLI never reads part of it
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize conflict report: {}", e))?;
+        atomic::write_file(path, json.as_bytes())
+    }
+
+    /// Add an outcome, dropping the oldest beyond the limit
+    pub fn record(&mut self, settled: SettledConflict) {
+        self.settled.push(settled);
+        let excess = self.settled.len().saturating_sub(REPORT_LIMIT);
+        self.settled.drain(..excess);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn conflict(remote: &str) -> Conflict {
+        Conflict {
+            local: PathBuf::from("/tmp/Dropbox").join(remote.trim_start_matches('/')),
+            remote: remote.to_string(),
+            base_rev: Some("1".to_string()),
+            local_size: 3,
+            local_modified: None,
+            remote_metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_queue_survives_reload_and_blocks_paths() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("conflicts.json");
+        let mut queue = ConflictQueue::default();
+        queue.push(conflict("/Notes/todo.md"), vec![PathBuf::from("/tmp/Dropbox/.conflicts/todo.md")]);
+        queue.push(conflict("/Notes/Todo.md"), Vec::new());
+        queue.push(conflict("/plan.md"), Vec::new());
+        queue.save(&path).unwrap();
+
+        let mut queue = ConflictQueue::load(&path).unwrap();
+        assert_eq!(queue.conflicts.len(), 2);
+        assert!(queue.blocks("/notes/todo.md"));
+        assert!(queue.blocks("/Notes"));
+        assert!(!queue.blocks("/Notes/other.md"));
+
+        assert!(queue.decide("/PLAN.md", ConflictPolicy::LocalWins));
+        assert_eq!(queue.get("/plan.md").unwrap().decision, Some(ConflictPolicy::LocalWins));
+        assert!(!queue.decide("/missing.md", ConflictPolicy::LocalWins));
+
+        let filter = ConflictFilter::new(Some("/notes/"), Some("*.MD")).unwrap();
+        assert_eq!(queue.select(&filter).count(), 1);
+        let decided = queue.decide_matching(&ConflictFilter::default(), ConflictPolicy::RemoteWins);
+        assert_eq!(decided.len(), 2);
+        assert!(queue.conflicts.iter().all(|queued| queued.decision == Some(ConflictPolicy::RemoteWins)));
+
+        let detected_at = queue.get("/plan.md").unwrap().detected_at;
+        queue.finish("/plan.md", detected_at, false);
+        assert_eq!(queue.get("/plan.md").unwrap().decision, None);
+        queue.push(conflict("/plan.md"), Vec::new());
+        queue.finish("/plan.md", detected_at, true);
+        assert!(queue.get("/plan.md").is_some());
+        let detected_at = queue.get("/plan.md").unwrap().detected_at;
+        queue.finish("/plan.md", detected_at, true);
+        assert!(queue.get("/plan.md").is_none());
+
+        queue.remove("/notes/todo.md");
+        queue.remove("/plan.md");
+        queue.save(&path).unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -1,10 +1,195 @@
-// TODO: Implement conflict resolution in future task
+use crate::Result;
+use crate::config::manager::{ConflictConfig, ConflictPolicy};
+use crate::dropbox::client::FileMetadata;
+use crate::sync::paths;
+use crate::utils::timestamps;
+use chrono::{DateTime, Utc};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+/// A file changed both locally and on Dropbox since it was last synced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conflict {
+    /// Local copy holding the unsynced edit
+    pub local: PathBuf,
+    /// Dropbox path of the file
+    pub remote: String,
+    /// Rev both sides last agreed on, from the sync index
+    pub base_rev: Option<String>,
+    pub local_size: u64,
+    pub local_modified: Option<DateTime<Utc>>,
+    /// Current Dropbox version, or `None` if the file was deleted there
+    pub remote_metadata: Option<FileMetadata>,
+}
+
+/// How the engine settles a conflict
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Save the local edit as a conflicted copy and take the Dropbox version
+    KeepBoth,
+    /// Upload the local version over the Dropbox one
+    KeepLocal,
+    /// Take the Dropbox version, moving the local edit to the conflict backups
+    KeepRemote,
+    /// Combine both edits against the last synced version, if they do not overlap
+    Merge,
+    /// Leave both sides alone until the user decides
+    Ask,
+}
+
+/// Decides how conflicts are settled, following the configured policies
 pub struct ConflictResolver {
-    // TODO: Add conflict resolver implementation
+    policy: ConflictPolicy,
+    rules: Vec<(Gitignore, ConflictPolicy)>,
+    host: String,
 }
 
 impl ConflictResolver {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(config: &ConflictConfig) -> Result<Self> {
+        let rules = config.rules.iter()
+            .map(|rule| Ok((pattern_matcher(&rule.pattern)?, rule.policy)))
+            .collect::<Result<_>>()?;
+        Ok(Self { policy: config.policy, rules, host: host_name() })
+    }
+
+    /// Policy for a Dropbox path: the first matching rule, else the default
+    pub fn policy_for(&self, remote: &str) -> ConflictPolicy {
+        self.rules.iter()
+            .find(|(matcher, _)| matches(matcher, remote))
+            .map(|(_, policy)| *policy)
+            .unwrap_or(self.policy)
+    }
+
+    /// Choose how to settle a conflict
+    pub fn resolve(&self, conflict: &Conflict) -> Resolution {
+        Self::apply_policy(self.policy_for(&conflict.remote), conflict)
+    }
+
+    /// What `policy` means for a particular conflict
+    pub fn apply_policy(policy: ConflictPolicy, conflict: &Conflict) -> Resolution {
+        match policy {
+            ConflictPolicy::KeepBoth => Resolution::KeepBoth,
+            ConflictPolicy::LocalWins => Resolution::KeepLocal,
+            ConflictPolicy::RemoteWins => Resolution::KeepRemote,
+            ConflictPolicy::Ask => Resolution::Ask,
+            ConflictPolicy::Merge => Resolution::Merge,
+            ConflictPolicy::NewestWins => newest(conflict),
+        }
+    }
+
+    /// Free name next to `local` for the conflicted copy of an edit made on this machine
+    ///
+    /// `notes.md` becomes `notes (conflicted copy from <host> <date>).md`,
+    /// numbered if that name is taken.
+    pub fn copy_path(&self, local: &Path) -> PathBuf {
+        let label = format!("conflicted copy from {} {}", self.host, chrono::Local::now().format("%Y-%m-%d"));
+        (1..)
+            .map(|n| match n {
+                1 => paths::labelled_sibling(local, &label),
+                n => paths::labelled_sibling(local, &format!("{} {}", label, n)),
+            })
+            .find(|candidate| std::fs::symlink_metadata(candidate).is_err())
+            .expect("an unused name exists")
     }
-} 
\ No newline at end of file
+}
+
+/// Case-insensitive matcher for a gitignore-style pattern over Dropbox paths
+pub(crate) fn pattern_matcher(pattern: &str) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new("/");
+    builder.case_insensitive(true)
+        .map_err(|e| anyhow::anyhow!("Failed to build conflict pattern matcher: {}", e))?;
+    builder.add_line(None, pattern)
+        .map_err(|e| anyhow::anyhow!("Invalid conflict pattern {}: {}", pattern, e))?;
+    builder.build()
+        .map_err(|e| anyhow::anyhow!("Failed to build conflict pattern matcher: {}", e))
+}
+
+/// Whether a Dropbox path or one of its folders matches
+pub(crate) fn matches(matcher: &Gitignore, remote: &str) -> bool {
+    matcher.matched_path_or_any_parents(Path::new(remote), false).is_ignore()
+}
+
+/// Keep the version with the later modification time; an edit wins over a deletion
+fn newest(conflict: &Conflict) -> Resolution {
+    let Some(remote) = &conflict.remote_metadata else {
+        return Resolution::KeepLocal;
+    };
+    let remote_modified = remote.client_modified.as_deref().and_then(timestamps::parse_dropbox_time);
+    match (conflict.local_modified, remote_modified) {
+        (Some(local), Some(remote)) => match local.cmp(&remote) {
+            Ordering::Greater => Resolution::KeepLocal,
+            Ordering::Less => Resolution::KeepRemote,
+            Ordering::Equal => Resolution::KeepBoth,
+        },
+        _ => Resolution::KeepBoth,
+    }
+}
+
+/// Name of this machine, as shown in conflicted copy names
+pub(crate) fn host_name() -> String {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer is writable for its full length
+    let rc = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    let name = (rc == 0)
+        .then(|| std::ffi::CStr::from_bytes_until_nul(&buffer).ok())
+        .flatten()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if name.is_empty() { "unknown host".to_string() } else { name }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::manager::ConflictRule;
+
+    fn conflict(remote: &str, local_modified: &str, remote_modified: &str) -> Conflict {
+        Conflict {
+            local: PathBuf::from("/tmp/Dropbox").join(remote.trim_start_matches('/')),
+            remote: remote.to_string(),
+            base_rev: Some("1".to_string()),
+            local_size: 1,
+            local_modified: timestamps::parse_dropbox_time(local_modified),
+            remote_metadata: Some(FileMetadata {
+                name: remote.rsplit('/').next().unwrap().to_string(),
+                path_lower: remote.to_lowercase(),
+                path_display: remote.to_string(),
+                id: "id:1".to_string(),
+                client_modified: Some(remote_modified.to_string()),
+                server_modified: None,
+                rev: "2".to_string(),
+                size: 2,
+                is_downloadable: true,
+                content_hash: None,
+                symlink_info: None,
+                tag: "file".to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_rules_override_the_default_policy() {
+        let resolver = ConflictResolver::new(&ConflictConfig {
+            policy: ConflictPolicy::NewestWins,
+            rules: vec![
+                ConflictRule { pattern: "*.md".to_string(), policy: ConflictPolicy::Ask },
+                ConflictRule { pattern: "build/".to_string(), policy: ConflictPolicy::RemoteWins },
+            ],
+            ..ConflictConfig::default()
+        }).unwrap();
+
+        let older = "2024-01-01T00:00:00Z";
+        let newer = "2024-06-01T00:00:00Z";
+        assert_eq!(resolver.resolve(&conflict("/Notes/Todo.MD", newer, older)), Resolution::Ask);
+        assert_eq!(resolver.resolve(&conflict("/app/build/out.bin", newer, older)), Resolution::KeepRemote);
+        assert_eq!(resolver.resolve(&conflict("/app/main.rs", newer, older)), Resolution::KeepLocal);
+        assert_eq!(resolver.resolve(&conflict("/app/main.rs", older, newer)), Resolution::KeepRemote);
+        assert_eq!(resolver.resolve(&conflict("/app/main.rs", older, older)), Resolution::KeepBoth);
+
+        let copy = resolver.copy_path(Path::new("/nonexistent/notes.md"));
+        let name = copy.file_name().unwrap().to_string_lossy();
+        assert!(name.starts_with("notes (conflicted copy from ") && name.ends_with(").md"));
+    }
+}
@@ -1,38 +1,51 @@
 use crate::Result;
+use crate::conflict::backup::Backup;
+use super::metadata_cache::MetadataCache;
 use reqwest::{Client, header};
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tracing::{debug, warn};
 
 /// Dropbox API v2 client for file operations
 pub struct DropboxClient {
     pub(crate) client: Client,
+    #[allow(dead_code)]
     access_token: String,
     pub(crate) base_url: String,
+    /// Host for endpoints that transfer file content
+    pub(crate) content_url: String,
+    /// Remote metadata seen in listings and responses, consulted before `get_metadata`
+    pub(crate) metadata: Mutex<MetadataCache>,
+    /// Local store for Dropbox versions replaced by conflicting uploads
+    pub(crate) backups: Option<Arc<Backup>>,
 }
 
 /// Dropbox API error response
 #[derive(Debug, Deserialize)]
-struct DropboxError {
-    error_summary: String,
+#[allow(dead_code)]
+pub(crate) struct DropboxError {
+    pub(crate) error_summary: String,
     error: DropboxErrorDetail,
 }
 
 #[derive(Debug, Deserialize)]
-struct DropboxErrorDetail {
+#[allow(dead_code)]
+pub(crate) struct DropboxErrorDetail {
     #[serde(rename = ".tag")]
     tag: String,
     reason: Option<DropboxErrorReason>,
 }
 
 #[derive(Debug, Deserialize)]
-struct DropboxErrorReason {
+#[allow(dead_code)]
+pub(crate) struct DropboxErrorReason {
     #[serde(rename = ".tag")]
     tag: String,
 }
 
 /// Dropbox file metadata
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct FileMetadata {
     pub name: String,
     pub path_lower: String,
@@ -44,12 +57,21 @@ pub struct FileMetadata {
     pub size: u64,
     pub is_downloadable: bool,
     pub content_hash: Option<String>,
+    /// Present when the file is a symbolic link
+    #[serde(default)]
+    pub symlink_info: Option<SymlinkInfo>,
     #[serde(rename = ".tag")]
     pub tag: String,
 }
 
+/// Target of a file stored on Dropbox as a symbolic link
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SymlinkInfo {
+    pub target: String,
+}
+
 /// Dropbox folder metadata
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct FolderMetadata {
     pub name: String,
     pub path_lower: String,
@@ -59,6 +81,64 @@ pub struct FolderMetadata {
     pub tag: String,
 }
 
+/// Dropbox metadata for an entry deleted since the last cursor
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeletedMetadata {
+    pub name: String,
+    pub path_lower: String,
+    pub path_display: String,
+    #[serde(rename = ".tag")]
+    pub tag: String,
+}
+
+/// A single entry returned by the list folder endpoints
+#[derive(Debug, Clone)]
+pub enum RemoteEntry {
+    File(FileMetadata),
+    Folder(FolderMetadata),
+    Deleted(DeletedMetadata),
+}
+
+impl RemoteEntry {
+    /// Parse a raw list folder entry using its `.tag` discriminator
+    pub fn from_value(value: serde_json::Value) -> Option<Self> {
+        match value.get(".tag").and_then(|t| t.as_str()) {
+            Some("file") => serde_json::from_value(value).ok().map(RemoteEntry::File),
+            Some("folder") => serde_json::from_value(value).ok().map(RemoteEntry::Folder),
+            Some("deleted") => serde_json::from_value(value).ok().map(RemoteEntry::Deleted),
+            _ => None,
+        }
+    }
+
+    /// Lowercased Dropbox path of the entry
+    pub fn path_lower(&self) -> &str {
+        match self {
+            RemoteEntry::File(m) => &m.path_lower,
+            RemoteEntry::Folder(m) => &m.path_lower,
+            RemoteEntry::Deleted(m) => &m.path_lower,
+        }
+    }
+
+    /// Display Dropbox path of the entry
+    pub fn path_display(&self) -> &str {
+        match self {
+            RemoteEntry::File(m) => &m.path_display,
+            RemoteEntry::Folder(m) => &m.path_display,
+            RemoteEntry::Deleted(m) => &m.path_display,
+        }
+    }
+}
+
+/// A page of remote changes together with the cursor to resume from
+#[derive(Debug, Clone)]
+pub struct RemoteDelta {
+    pub entries: Vec<RemoteEntry>,
+    pub cursor: String,
+}
+
+/// Outcome of a single relocation inside a move batch, with the failure reason on error
+pub type MoveResult = std::result::Result<RemoteEntry, String>;
+
 /// Dropbox list folder response
 #[derive(Debug, Deserialize)]
 struct ListFolderResponse {
@@ -88,13 +168,35 @@ impl DropboxClient {
             client,
             access_token: access_token.to_string(),
             base_url: "https://api.dropboxapi.com/2".to_string(),
+            content_url: "https://content.dropboxapi.com/2".to_string(),
+            metadata: Mutex::new(MetadataCache::default()),
+            backups: None,
         })
     }
 
+    /// Keep Dropbox versions replaced by conflicting uploads in `store`
+    pub fn with_backup_store(self, store: Arc<Backup>) -> Self {
+        Self { backups: Some(store), ..self }
+    }
+
+    /// Use a remote metadata cache loaded from disk instead of an empty one
+    pub fn with_metadata_cache(self, cache: MetadataCache) -> Self {
+        Self { metadata: Mutex::new(cache), ..self }
+    }
+
+    /// Persist the remote metadata cache
+    pub fn save_metadata_cache(&self) -> Result<()> {
+        self.metadata_cache().save()
+    }
+
+    pub(crate) fn metadata_cache(&self) -> std::sync::MutexGuard<'_, MetadataCache> {
+        self.metadata.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
     /// Test the connection and token validity
     pub async fn test_connection(&self) -> Result<()> {
         let response = self.client
-            .post(&format!("{}/users/get_current_account", self.base_url))
+            .post(format!("{}/users/get_current_account", self.base_url))
             .send()
             .await
             .map_err(|e| anyhow::anyhow!("Failed to test connection: {}", e))?;
@@ -109,6 +211,15 @@ impl DropboxClient {
         Ok(())
     }
 
+    /// Whether the Dropbox API can be reached at all, whatever it answers
+    pub async fn is_reachable(&self) -> bool {
+        self.client
+            .post(format!("{}/users/get_current_account", self.base_url))
+            .send()
+            .await
+            .is_ok()
+    }
+
     /// Get file metadata
     pub async fn get_metadata(&self, path: &str) -> Result<FileMetadata> {
         let payload = serde_json::json!({
@@ -119,7 +230,7 @@ impl DropboxClient {
         });
 
         let response = self.client
-            .post(&format!("{}/files/get_metadata", self.base_url))
+            .post(format!("{}/files/get_metadata", self.base_url))
             .json(&payload)
             .send()
             .await
@@ -136,11 +247,12 @@ impl DropboxClient {
 
         debug!("Retrieved metadata for {}: size={}, modified={:?}", 
                path, metadata.size, metadata.server_modified);
+        self.metadata_cache().record(&metadata);
         Ok(metadata)
     }
 
-    /// List folder contents
-    pub async fn list_folder(&self, path: &str) -> Result<Vec<FileMetadata>> {
+    /// List folder contents, files and folders alike
+    pub async fn list_folder(&self, path: &str) -> Result<Vec<RemoteEntry>> {
         let mut all_entries = Vec::new();
         let mut cursor = None;
 
@@ -168,7 +280,7 @@ impl DropboxClient {
             };
 
             let response = self.client
-                .post(&format!("{}{}", self.base_url, endpoint))
+                .post(format!("{}{}", self.base_url, endpoint))
                 .json(&payload)
                 .send()
                 .await
@@ -183,15 +295,10 @@ impl DropboxClient {
             let list_response: ListFolderResponse = response.json().await
                 .map_err(|e| anyhow::anyhow!("Failed to parse list response: {}", e))?;
 
-            // Parse entries into FileMetadata or FolderMetadata
             for entry in list_response.entries {
-                if let Ok(file_metadata) = serde_json::from_value::<FileMetadata>(entry.clone()) {
-                    all_entries.push(file_metadata);
-                } else if let Ok(_folder_metadata) = serde_json::from_value::<FolderMetadata>(entry.clone()) {
-                    // Skip folders for now, we'll handle them separately
-                    continue;
-                } else {
-                    warn!("Failed to parse folder entry: {:?}", entry);
+                match RemoteEntry::from_value(entry.clone()) {
+                    Some(parsed) => all_entries.push(parsed),
+                    None => warn!("Failed to parse folder entry: {:?}", entry),
                 }
             }
 
@@ -202,7 +309,7 @@ impl DropboxClient {
             cursor = Some(list_response.cursor);
         }
 
-        debug!("Listed {} files in folder {}", all_entries.len(), path);
+        debug!("Listed {} entries in folder {}", all_entries.len(), path);
         Ok(all_entries)
     }
 
@@ -218,7 +325,7 @@ impl DropboxClient {
         }
 
         let response = self.client
-            .post(&format!("{}/files/get_temporary_link", self.base_url))
+            .post(format!("{}/files/get_temporary_link", self.base_url))
             .json(&payload)
             .send()
             .await
@@ -237,6 +344,32 @@ impl DropboxClient {
         Ok(temp_link.link)
     }
 
+    /// Download an earlier revision of a file by its rev
+    pub async fn download_revision(&self, rev: &str) -> Result<Vec<u8>> {
+        let payload = serde_json::json!({
+            "path": format!("rev:{}", rev)
+        });
+
+        let response = self.client
+            .post(format!("{}/files/download", self.content_url))
+            .header("Dropbox-API-Arg", serde_json::to_string(&payload)?)
+            .header("Content-Type", "application/octet-stream")
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to download revision {}: {}", rev, e))?;
+
+        if !response.status().is_success() {
+            let error: DropboxError = response.json().await
+                .map_err(|e| anyhow::anyhow!("Failed to parse error response: {}", e))?;
+            return Err(anyhow::anyhow!("Failed to download revision {}: {}", rev, error.error_summary));
+        }
+
+        let content = response.bytes().await
+            .map_err(|e| anyhow::anyhow!("Failed to read revision {}: {}", rev, e))?;
+        debug!("Downloaded revision {}: {} bytes", rev, content.len());
+        Ok(content.to_vec())
+    }
+
     /// Upload a file to Dropbox
     pub async fn upload_file(&self, path: &str, content: &[u8]) -> Result<FileMetadata> {
         let payload = serde_json::json!({
@@ -248,7 +381,7 @@ impl DropboxClient {
         });
 
         let response = self.client
-            .post(&format!("{}/files/upload", self.base_url))
+            .post(format!("{}/files/upload", self.content_url))
             .header("Dropbox-API-Arg", serde_json::to_string(&payload)?)
             .header("Content-Type", "application/octet-stream")
             .body(content.to_vec())
@@ -266,8 +399,323 @@ impl DropboxClient {
             .map_err(|e| anyhow::anyhow!("Failed to parse upload response: {}", e))?;
 
         debug!("Uploaded file {}: size={}", path, metadata.size);
+        self.metadata_cache().record(&metadata);
         Ok(metadata)
     }
+
+    /// List every entry below a folder, returning a cursor for later deltas
+    pub async fn list_folder_recursive(&self, path: &str) -> Result<RemoteDelta> {
+        let payload = serde_json::json!({
+            "path": path,
+            "recursive": true,
+            "include_media_info": false,
+            "include_deleted": false,
+            "include_has_explicit_shared_members": false,
+            "include_mounted_folders": true,
+            "limit": 1000
+        });
+
+        let (mut page, mut has_more) = self.list_folder_page("/files/list_folder", &payload).await?;
+        let mut entries = std::mem::take(&mut page.entries);
+        let mut cursor = page.cursor;
+        while has_more {
+            let payload = serde_json::json!({ "cursor": cursor });
+            let (mut next, more) = self.list_folder_page("/files/list_folder/continue", &payload).await?;
+            entries.append(&mut next.entries);
+            cursor = next.cursor;
+            has_more = more;
+        }
+
+        debug!("Listed {} entries recursively under {}", entries.len(), path);
+        self.metadata_cache().apply_listing(path, &entries);
+        Ok(RemoteDelta { entries, cursor })
+    }
+
+    /// Fetch all changes recorded since the given cursor
+    pub async fn list_folder_continue(&self, cursor: &str) -> Result<RemoteDelta> {
+        let mut entries = Vec::new();
+        let mut cursor = cursor.to_string();
+
+        loop {
+            let payload = serde_json::json!({ "cursor": cursor });
+            let (mut page, has_more) = self.list_folder_page("/files/list_folder/continue", &payload).await?;
+            entries.append(&mut page.entries);
+            cursor = page.cursor;
+            if !has_more {
+                break;
+            }
+        }
+
+        debug!("Fetched {} remote changes", entries.len());
+        self.metadata_cache().apply_delta(&entries);
+        Ok(RemoteDelta { entries, cursor })
+    }
+
+    /// Fetch a single list folder page, returning it with the `has_more` flag
+    async fn list_folder_page(&self, endpoint: &str, payload: &serde_json::Value) -> Result<(RemoteDelta, bool)> {
+        let response = self.client
+            .post(format!("{}{}", self.base_url, endpoint))
+            .json(payload)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list folder changes: {}", e))?;
+
+        if !response.status().is_success() {
+            let error: DropboxError = response.json().await
+                .map_err(|e| anyhow::anyhow!("Failed to parse error response: {}", e))?;
+            return Err(anyhow::anyhow!("Failed to list folder: {}", error.error_summary));
+        }
+
+        let list_response: ListFolderResponse = response.json().await
+            .map_err(|e| anyhow::anyhow!("Failed to parse list response: {}", e))?;
+
+        let mut entries = Vec::with_capacity(list_response.entries.len());
+        for entry in list_response.entries {
+            match RemoteEntry::from_value(entry.clone()) {
+                Some(parsed) => entries.push(parsed),
+                None => warn!("Failed to parse folder entry: {:?}", entry),
+            }
+        }
+
+        Ok((RemoteDelta { entries, cursor: list_response.cursor }, list_response.has_more))
+    }
+
+    /// Move a file or folder to a new path, keeping its Dropbox id
+    pub async fn move_entry(&self, from_path: &str, to_path: &str) -> Result<RemoteEntry> {
+        let payload = serde_json::json!({
+            "from_path": from_path,
+            "to_path": to_path,
+            "allow_shared_folder": true,
+            "autorename": false,
+            "allow_ownership_transfer": false
+        });
+
+        #[derive(Deserialize)]
+        struct RelocationResult {
+            metadata: serde_json::Value,
+        }
+
+        let response = self.client
+            .post(format!("{}/files/move_v2", self.base_url))
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to move {} to {}: {}", from_path, to_path, e))?;
+
+        if !response.status().is_success() {
+            let error: DropboxError = response.json().await
+                .map_err(|e| anyhow::anyhow!("Failed to parse error response: {}", e))?;
+            return Err(anyhow::anyhow!("Failed to move {}: {}", from_path, error.error_summary));
+        }
+
+        let result: RelocationResult = response.json().await
+            .map_err(|e| anyhow::anyhow!("Failed to parse move response: {}", e))?;
+        let entry = RemoteEntry::from_value(result.metadata)
+            .ok_or_else(|| anyhow::anyhow!("Unexpected metadata in move response for {}", to_path))?;
+
+        debug!("Moved {} to {}", from_path, to_path);
+        self.metadata_cache().rename(from_path, to_path);
+        Ok(entry)
+    }
+
+    /// Create a folder, returning the existing one if the path is already a folder
+    pub async fn create_folder(&self, path: &str) -> Result<FolderMetadata> {
+        let payload = serde_json::json!({ "path": path, "autorename": false });
+
+        #[derive(Deserialize)]
+        struct CreateFolderResult {
+            metadata: FolderMetadata,
+        }
+
+        let response = self.client
+            .post(format!("{}/files/create_folder_v2", self.base_url))
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create folder {}: {}", path, e))?;
+
+        if !response.status().is_success() {
+            let error: DropboxError = response.json().await
+                .map_err(|e| anyhow::anyhow!("Failed to parse error response: {}", e))?;
+            if error.error_summary.contains("conflict/folder") {
+                debug!("{} already exists", path);
+                return match self.get_entry(path).await? {
+                    Some(RemoteEntry::Folder(metadata)) => Ok(metadata),
+                    _ => Err(anyhow::anyhow!("{} is not a folder", path)),
+                };
+            }
+            return Err(anyhow::anyhow!("Failed to create folder {}: {}", path, error.error_summary));
+        }
+
+        let result: CreateFolderResult = response.json().await
+            .map_err(|e| anyhow::anyhow!("Failed to parse create folder response: {}", e))?;
+        debug!("Created folder {}", path);
+        Ok(result.metadata)
+    }
+
+    /// Get the metadata of a file or folder, or `None` if nothing exists at `path`
+    pub async fn get_entry(&self, path: &str) -> Result<Option<RemoteEntry>> {
+        let response = self.client
+            .post(format!("{}/files/get_metadata", self.base_url))
+            .json(&serde_json::json!({ "path": path }))
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get metadata for {}: {}", path, e))?;
+
+        if !response.status().is_success() {
+            let error: DropboxError = response.json().await
+                .map_err(|e| anyhow::anyhow!("Failed to parse error response: {}", e))?;
+            if error.error_summary.contains("not_found") {
+                return Ok(None);
+            }
+            return Err(anyhow::anyhow!("Failed to get metadata: {}", error.error_summary));
+        }
+
+        let value: serde_json::Value = response.json().await
+            .map_err(|e| anyhow::anyhow!("Failed to parse metadata for {}: {}", path, e))?;
+        let entry = RemoteEntry::from_value(value)
+            .ok_or_else(|| anyhow::anyhow!("Unexpected metadata for {}", path))?;
+        if let RemoteEntry::File(metadata) = &entry {
+            self.metadata_cache().record(metadata);
+        }
+        Ok(Some(entry))
+    }
+
+    /// Delete a file or folder, treating an already missing path as success
+    ///
+    /// With `parent_rev`, a file is only deleted while Dropbox still has that rev.
+    pub async fn delete_entry(&self, path: &str, parent_rev: Option<&str>) -> Result<()> {
+        let mut payload = serde_json::json!({ "path": path });
+        if let Some(rev) = parent_rev {
+            payload["parent_rev"] = serde_json::json!(rev);
+        }
+
+        let response = self.client
+            .post(format!("{}/files/delete_v2", self.base_url))
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to delete {}: {}", path, e))?;
+
+        if !response.status().is_success() {
+            let error: DropboxError = response.json().await
+                .map_err(|e| anyhow::anyhow!("Failed to parse error response: {}", e))?;
+            if error.error_summary.contains("not_found") {
+                debug!("{} was already deleted", path);
+                self.metadata_cache().forget(path);
+                return Ok(());
+            }
+            if let (true, Some(rev)) = (error.error_summary.contains("conflict"), parent_rev) {
+                return Err(anyhow::anyhow!("Not deleting {}: it changed on Dropbox since rev {}", path, rev));
+            }
+            return Err(anyhow::anyhow!("Failed to delete {}: {}", path, error.error_summary));
+        }
+
+        debug!("Deleted {}", path);
+        self.metadata_cache().forget(path);
+        Ok(())
+    }
+
+    /// Move several entries in one request, waiting for the batch job to finish
+    ///
+    /// Results are returned in the same order as `moves`.
+    pub async fn move_batch(&self, moves: &[(String, String)]) -> Result<Vec<MoveResult>> {
+        let relocations: Vec<_> = moves
+            .iter()
+            .map(|(from, to)| serde_json::json!({ "from_path": from, "to_path": to }))
+            .collect();
+        let payload = serde_json::json!({
+            "entries": relocations,
+            "autorename": false,
+            "allow_ownership_transfer": false
+        });
+
+        let response = self.client
+            .post(format!("{}/files/move_batch_v2", self.base_url))
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to start move batch: {}", e))?;
+
+        if !response.status().is_success() {
+            let error: DropboxError = response.json().await
+                .map_err(|e| anyhow::anyhow!("Failed to parse error response: {}", e))?;
+            return Err(anyhow::anyhow!("Failed to start move batch: {}", error.error_summary));
+        }
+
+        let mut status: serde_json::Value = response.json().await
+            .map_err(|e| anyhow::anyhow!("Failed to parse move batch response: {}", e))?;
+
+        let mut job_id: Option<String> = None;
+        let mut delay = Duration::from_millis(250);
+        loop {
+            match status.get(".tag").and_then(|t| t.as_str()) {
+                Some("complete") => break,
+                Some("async_job_id") => {
+                    job_id = status.get("async_job_id").and_then(|j| j.as_str()).map(str::to_string);
+                }
+                Some("in_progress") => {}
+                Some("failed") => return Err(anyhow::anyhow!("Move batch failed: {}", status)),
+                _ => return Err(anyhow::anyhow!("Unexpected move batch status: {}", status)),
+            }
+
+            let id = job_id.as_deref()
+                .ok_or_else(|| anyhow::anyhow!("Move batch response is missing a job id"))?;
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(Duration::from_secs(5));
+            status = self.check_move_batch(id).await?;
+        }
+
+        let results = status.get("entries")
+            .and_then(|e| e.as_array())
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| match entry.get(".tag").and_then(|t| t.as_str()) {
+                Some("success") => entry.get("success")
+                    .cloned()
+                    .and_then(RemoteEntry::from_value)
+                    .ok_or_else(|| "missing metadata".to_string()),
+                _ => Err(entry.get("failure").map(|f| f.to_string()).unwrap_or_else(|| entry.to_string())),
+            })
+            .collect::<Vec<MoveResult>>();
+
+        if results.len() != moves.len() {
+            return Err(anyhow::anyhow!(
+                "Move batch returned {} results for {} entries", results.len(), moves.len()
+            ));
+        }
+
+        debug!("Moved {} entries in batch", results.len());
+        {
+            let mut cache = self.metadata_cache();
+            for ((from, to), _) in moves.iter().zip(&results).filter(|(_, result)| result.is_ok()) {
+                cache.rename(from, to);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Poll the status of an asynchronous move batch job
+    async fn check_move_batch(&self, async_job_id: &str) -> Result<serde_json::Value> {
+        let payload = serde_json::json!({ "async_job_id": async_job_id });
+
+        let response = self.client
+            .post(format!("{}/files/move_batch/check_v2", self.base_url))
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to check move batch {}: {}", async_job_id, e))?;
+
+        if !response.status().is_success() {
+            let error: DropboxError = response.json().await
+                .map_err(|e| anyhow::anyhow!("Failed to parse error response: {}", e))?;
+            return Err(anyhow::anyhow!("Failed to check move batch: {}", error.error_summary));
+        }
+
+        response.json().await
+            .map_err(|e| anyhow::anyhow!("Failed to parse move batch status: {}", e))
+    }
 }
 
 #[cfg(test)]
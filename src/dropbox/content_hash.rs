@@ -0,0 +1,77 @@
+use crate::Result;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Size of the blocks hashed separately by the Dropbox content hash
+pub const BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Dropbox content hash of a byte slice
+///
+/// Each 4 MiB block is hashed with SHA-256 and the hash of the
+/// concatenated block hashes is returned as lowercase hex.
+pub fn content_hash(data: &[u8]) -> String {
+    let mut overall = Sha256::new();
+    for block in data.chunks(BLOCK_SIZE) {
+        overall.update(Sha256::digest(block));
+    }
+    hex(&overall.finalize())
+}
+
+/// Dropbox content hash of a local file, read block by block
+pub fn file_content_hash(path: &Path) -> Result<String> {
+    let mut file = File::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+    let mut overall = Sha256::new();
+    let mut block = vec![0; BLOCK_SIZE];
+    loop {
+        let filled = read_block(&mut file, &mut block)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        if filled == 0 {
+            break;
+        }
+        overall.update(Sha256::digest(&block[..filled]));
+        if filled < BLOCK_SIZE {
+            break;
+        }
+    }
+    Ok(hex(&overall.finalize()))
+}
+
+/// Fill `block` as far as the file allows, returning how many bytes were read
+fn read_block(file: &mut File, block: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < block.len() {
+        match file.read(&mut block[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_file_hash_matches_block_scheme() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("data.bin");
+        let data: Vec<u8> = (0..BLOCK_SIZE + 10).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let mut expected = Sha256::new();
+        expected.update(Sha256::digest(&data[..BLOCK_SIZE]));
+        expected.update(Sha256::digest(&data[BLOCK_SIZE..]));
+        assert_eq!(file_content_hash(&path).unwrap(), hex(&expected.finalize()));
+        assert_eq!(content_hash(&data), file_content_hash(&path).unwrap());
+        assert_eq!(content_hash(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{ConfigManager, Result};
+use crate::sync::{atomic, paths};
+use super::client::{FileMetadata, RemoteEntry};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::debug;
+
+/// How long a listing answers lookups without a newer delta (5 minutes)
+pub const MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// What the cache knows about a remote path
+#[derive(Debug, Clone)]
+pub enum Cached {
+    /// A file exists with this metadata
+    Present(Box<FileMetadata>),
+    /// No file exists at the path
+    Absent,
+    /// The cache is stale or never listed the path; ask Dropbox
+    Unknown,
+}
+
+/// Remote file metadata fed by recursive listings and the cursor delta feed
+///
+/// Lets conflict checks skip a `get_metadata` call per upload while the
+/// last listing or delta is recent.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MetadataCache {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// Files by index key
+    files: HashMap<String, FileMetadata>,
+    /// Whether the files come from a listing of the whole Dropbox, so missing means absent
+    complete: bool,
+    /// When the last full listing or delta was applied
+    synced_at: Option<DateTime<Utc>>,
+}
+
+impl MetadataCache {
+    /// Location of the cache inside the data directory
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(ConfigManager::data_dir()?.join("remote_metadata.json"))
+    }
+
+    /// Load the cache, starting empty if none was saved or it cannot be read
+    pub fn load(path: &Path) -> Self {
+        let mut cache: Self = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        debug!("Loaded {} cached remote files", cache.files.len());
+        cache.path = Some(path.to_path_buf());
+        cache
+    }
+
+    /// Save the cache where it was loaded from; caches built in memory are not saved
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize remote metadata cache: {}", e))?;
+        atomic::write_file(path, json.as_bytes())
+    }
+
+    /// Replace the cached files below `folder` with a fresh recursive listing of it
+    pub fn apply_listing(&mut self, folder: &str, entries: &[RemoteEntry]) {
+        let folder = paths::index_key(folder);
+        self.files.retain(|key, _| !folder.is_empty() && !paths::is_within(key, &folder));
+        self.apply(entries);
+        if folder.is_empty() {
+            self.complete = true;
+            self.synced_at = Some(Utc::now());
+        }
+    }
+
+    /// Apply the changes fetched with the cursor of a full listing
+    pub fn apply_delta(&mut self, entries: &[RemoteEntry]) {
+        self.apply(entries);
+        self.synced_at = Some(Utc::now());
+    }
+
+    fn apply(&mut self, entries: &[RemoteEntry]) {
+        for entry in entries {
+            let key = paths::index_key(entry.path_lower());
+            match entry {
+                RemoteEntry::File(metadata) => {
+                    self.files.insert(key, metadata.clone());
+                }
+                RemoteEntry::Folder(_) => {
+                    self.files.remove(&key);
+                }
+                RemoteEntry::Deleted(_) => self.forget(&key),
+            }
+        }
+    }
+
+    /// Record the metadata Dropbox returned for a single file
+    pub fn record(&mut self, metadata: &FileMetadata) {
+        self.files.insert(paths::index_key(&metadata.path_lower), metadata.clone());
+    }
+
+    /// Drop a deleted path and everything below it
+    pub fn forget(&mut self, path: &str) {
+        let key = paths::index_key(path);
+        self.files.retain(|cached, _| !paths::is_within(cached, &key));
+    }
+
+    /// Move the cached files at or below `from` to `to`
+    pub fn rename(&mut self, from: &str, to: &str) {
+        let (from, to) = (paths::index_key(from), paths::index_key(to));
+        let moved: Vec<String> = self.files.keys()
+            .filter(|key| paths::is_within(key, &from))
+            .cloned()
+            .collect();
+        for key in moved {
+            if let (Some(metadata), Some(new_key)) = (self.files.remove(&key), paths::rebase(&key, &from, &to)) {
+                self.files.insert(new_key, metadata);
+            }
+        }
+    }
+
+    /// Look up a path, answering only while the cache is recent
+    pub fn lookup(&self, path: &str) -> Cached {
+        let fresh = self.synced_at.is_some_and(|synced| {
+            Utc::now().signed_duration_since(synced).to_std().map_or(true, |age| age < MAX_AGE)
+        });
+        if !fresh {
+            return Cached::Unknown;
+        }
+        match self.files.get(&paths::index_key(path)) {
+            Some(metadata) => Cached::Present(Box::new(metadata.clone())),
+            None if self.complete => Cached::Absent,
+            None => Cached::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dropbox::client::DeletedMetadata;
+
+    fn file(path: &str, rev: &str) -> FileMetadata {
+        FileMetadata {
+            name: path.rsplit('/').next().unwrap().to_string(),
+            path_lower: path.to_lowercase(),
+            path_display: path.to_string(),
+            id: format!("id:{}", path),
+            client_modified: None,
+            server_modified: None,
+            rev: rev.to_string(),
+            size: 1,
+            is_downloadable: true,
+            content_hash: None,
+            symlink_info: None,
+            tag: "file".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_listings_and_deltas_answer_lookups() {
+        let mut cache = MetadataCache::default();
+        cache.record(&file("/Docs/a.txt", "1"));
+        assert!(matches!(cache.lookup("/docs/a.txt"), Cached::Unknown));
+
+        cache.apply_listing("", &[
+            RemoteEntry::File(file("/Docs/a.txt", "1")),
+            RemoteEntry::File(file("/Docs/b.txt", "1")),
+        ]);
+        assert!(matches!(cache.lookup("/Docs/A.txt"), Cached::Present(m) if m.rev == "1"));
+        assert!(matches!(cache.lookup("/Docs/c.txt"), Cached::Absent));
+
+        cache.apply_delta(&[
+            RemoteEntry::File(file("/Docs/a.txt", "2")),
+            RemoteEntry::Deleted(DeletedMetadata {
+                name: "b.txt".to_string(),
+                path_lower: "/docs/b.txt".to_string(),
+                path_display: "/Docs/b.txt".to_string(),
+                tag: "deleted".to_string(),
+            }),
+        ]);
+        assert!(matches!(cache.lookup("/docs/a.txt"), Cached::Present(m) if m.rev == "2"));
+        assert!(matches!(cache.lookup("/docs/b.txt"), Cached::Absent));
+
+        cache.rename("/Docs", "/Papers");
+        assert!(matches!(cache.lookup("/papers/a.txt"), Cached::Present(_)));
+
+        cache.synced_at = Some(Utc::now() - chrono::Duration::minutes(10));
+        assert!(matches!(cache.lookup("/papers/a.txt"), Cached::Unknown));
+    }
+}
@@ -1,5 +1,8 @@
 pub mod client;
+pub mod content_hash;
+pub mod metadata_cache;
 pub mod operations;
+pub mod properties;
 
 pub use client::DropboxClient;
 pub use operations::FileOperations; 
\ No newline at end of file
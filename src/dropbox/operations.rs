@@ -1,12 +1,19 @@
 use crate::Result;
-use super::client::DropboxClient;
-use std::path::Path;
+use crate::conflict::backup::{BackupInfo, BackupSource};
+use crate::sync::ignore_rules::IgnoreRules;
+use crate::sync::scanner::{EntryKind, Scanner};
+use crate::sync::{names, paths};
+use super::client::{DropboxClient, DropboxError, FileMetadata};
+use super::metadata_cache::Cached;
+use std::path::{Path, PathBuf};
 use std::fs;
+use std::sync::Arc;
 use tracing::{info, warn, debug};
 use chrono::{DateTime, Utc};
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 
 /// File operations trait for Dropbox
+#[allow(async_fn_in_trait)]
 pub trait FileOperations {
     /// Download a file from Dropbox
     async fn download_file(&self, path: &str) -> Result<Vec<u8>>;
@@ -36,6 +43,30 @@ pub struct UploadOptions {
     pub create_backup: bool,
     pub autorename: bool,
     pub mute: bool,
+    /// Rev the local content was last synced at
+    ///
+    /// When set, the upload only replaces the file if Dropbox still has this
+    /// rev and fails with [`UploadConflict`] otherwise.
+    pub parent_rev: Option<String>,
+}
+
+impl UploadOptions {
+    /// Dropbox write mode; a known rev makes Dropbox refuse to replace newer content
+    fn write_mode(&self) -> serde_json::Value {
+        match &self.parent_rev {
+            Some(rev) => serde_json::json!({ ".tag": "update", "update": rev }),
+            None if self.overwrite => serde_json::json!("overwrite"),
+            None => serde_json::json!("add"),
+        }
+    }
+}
+
+/// An upload based on a rev Dropbox no longer has, because the file changed there
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{path} changed on Dropbox since rev {parent_rev}")]
+pub struct UploadConflict {
+    pub path: String,
+    pub parent_rev: String,
 }
 
 impl Default for UploadOptions {
@@ -45,6 +76,7 @@ impl Default for UploadOptions {
             create_backup: true,
             autorename: false,
             mute: false,
+            parent_rev: None,
         }
     }
 }
@@ -59,8 +91,8 @@ enum UploadTask {
     },
     Backup {
         original_path: String,
-        backup_path: String,
         content: Vec<u8>,
+        content_hash: Option<String>,
     },
 }
 
@@ -78,13 +110,16 @@ impl FileOperations for DropboxClient {
         Ok(content.to_vec())
     }
     async fn upload_file(&self, path: &str, content: &[u8]) -> Result<()> {
-        self.upload_file_with_options(path, content, &UploadOptions::default()).await
+        self.upload_file_with_options(path, content, &UploadOptions::default()).await.map(|_| ())
     }
 }
 
 impl DropboxClient {
     /// Upload a file with conflict detection and backup using a work queue
-    pub async fn upload_file_with_options(&self, path: &str, content: &[u8], options: &UploadOptions) -> Result<()> {
+    ///
+    /// Returns the metadata Dropbox recorded for the uploaded file.
+    pub async fn upload_file_with_options(&self, path: &str, content: &[u8], options: &UploadOptions) -> Result<FileMetadata> {
+        let mut uploaded = None;
         let mut queue = VecDeque::new();
         queue.push_back(UploadTask::Upload {
             path: path.to_string(),
@@ -97,18 +132,17 @@ impl DropboxClient {
                 UploadTask::Upload { path, content, options } => {
                     // Conflict detection
                     if !options.overwrite && options.create_backup {
-                        match self.detect_conflict(&path, &content).await? {
-                            ConflictResult::Conflict { local_size, remote_size, local_modified, remote_modified, .. } => {
+                        match self.detect_conflict(&path, &content, options.parent_rev.as_deref()).await? {
+                            ConflictResult::Conflict { local_size, remote_size, local_modified, remote_modified, remote_hash, .. } => {
                                 warn!("Conflict detected for {}: local={} bytes ({:?}), remote={} bytes ({:?})", 
                                     path, local_size, local_modified, remote_size, remote_modified);
                                 if options.create_backup {
                                     // Enqueue backup task
-                                    let backup_path = format!("{}.backup.{}", path, chrono::Utc::now().timestamp());
                                     let remote_content = self.download_file(&path).await?;
                                     queue.push_back(UploadTask::Backup {
                                         original_path: path.clone(),
-                                        backup_path: backup_path.clone(),
                                         content: remote_content,
+                                        content_hash: remote_hash,
                                     });
                                 }
                                 if !options.autorename {
@@ -127,13 +161,13 @@ impl DropboxClient {
                     // Prepare upload payload
                     let payload = serde_json::json!({
                         "path": path,
-                        "mode": if options.overwrite { "overwrite" } else { "add" },
+                        "mode": options.write_mode(),
                         "autorename": options.autorename,
                         "mute": options.mute,
-                        "strict_conflict": false
+                        "strict_conflict": options.parent_rev.is_some()
                     });
                     let response = self.client
-                        .post(&format!("{}/files/upload", self.base_url))
+                        .post(format!("{}/files/upload", self.content_url))
                         .header("Dropbox-API-Arg", serde_json::to_string(&payload)?)
                         .header("Content-Type", "application/octet-stream")
                         .body(content.clone())
@@ -143,37 +177,33 @@ impl DropboxClient {
                     if !response.status().is_success() {
                         let status = response.status();
                         let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+                        let conflicted = serde_json::from_str::<DropboxError>(&error_text)
+                            .is_ok_and(|error| error.error_summary.contains("conflict"));
+                        if let (true, Some(parent_rev)) = (conflicted, &options.parent_rev) {
+                            warn!("{} changed on Dropbox since rev {}; not replacing it", path, parent_rev);
+                            return Err(UploadConflict { path, parent_rev: parent_rev.clone() }.into());
+                        }
                         return Err(anyhow::anyhow!("Failed to upload file {}: HTTP {} - {}", path, status, error_text));
                     }
+                    let metadata: FileMetadata = response.json().await
+                        .map_err(|e| anyhow::anyhow!("Failed to parse upload response: {}", e))?;
                     info!("Uploaded file {}: {} bytes", path, content.len());
+                    self.metadata_cache().record(&metadata);
+                    uploaded = Some(metadata);
                 }
-                UploadTask::Backup { original_path, backup_path, content } => {
-                    // Upload backup without conflict detection or further backup
-                    let payload = serde_json::json!({
-                        "path": backup_path,
-                        "mode": "overwrite",
-                        "autorename": false,
-                        "mute": true,
-                        "strict_conflict": false
-                    });
-                    let response = self.client
-                        .post(&format!("{}/files/upload", self.base_url))
-                        .header("Dropbox-API-Arg", serde_json::to_string(&payload)?)
-                        .header("Content-Type", "application/octet-stream")
-                        .body(content.clone())
-                        .send()
-                        .await
-                        .map_err(|e| anyhow::anyhow!("Failed to upload backup file {}: {}", backup_path, e))?;
-                    if !response.status().is_success() {
-                        let status = response.status();
-                        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                        return Err(anyhow::anyhow!("Failed to upload backup file {}: HTTP {} - {}", backup_path, status, error_text));
-                    }
-                    info!("Created backup of {} as {} ({} bytes)", original_path, backup_path, content.len());
+                UploadTask::Backup { original_path, content, content_hash } => {
+                    // Keep the Dropbox version in the local backup store rather than next to the file on Dropbox
+                    let Some(store) = &self.backups else {
+                        warn!("No backup store configured; not keeping the Dropbox version of {}", original_path);
+                        continue;
+                    };
+                    let info = BackupInfo { source: Some(BackupSource::Dropbox), rev: None, content_hash };
+                    let stored = store.store(&content, Path::new(original_path.trim_start_matches('/')), info)?;
+                    info!("Created backup of {} as {} ({} bytes)", original_path, stored.display(), content.len());
                 }
             }
         }
-        Ok(())
+        uploaded.ok_or_else(|| anyhow::anyhow!("Upload of {} produced no metadata", path))
     }
 
     pub async fn upload_local_file(&self, local_path: &Path, remote_path: &str) -> Result<()> {
@@ -185,75 +215,99 @@ impl DropboxClient {
         }
         let content = fs::read(local_path)
             .map_err(|e| anyhow::anyhow!("Failed to read local file {}: {}", local_path.display(), e))?;
-        self.upload_file_with_options(remote_path, &content, &UploadOptions::default()).await
+        self.upload_file_with_options(remote_path, &content, &UploadOptions::default()).await.map(|_| ())
     }
 
-    async fn detect_conflict(&self, path: &str, local_content: &[u8]) -> Result<ConflictResult> {
-        match self.get_metadata(path).await {
-            Ok(remote_metadata) => {
-                let local_size = local_content.len() as u64;
-                let remote_size = remote_metadata.size;
-                if local_size != remote_size {
-                    return Ok(ConflictResult::Conflict {
-                        local_size,
-                        remote_size,
-                        local_modified: None,
-                        remote_modified: remote_metadata.server_modified
-                            .as_ref()
-                            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-                            .map(|dt| dt.with_timezone(&Utc)),
-                        local_hash: None,
-                        remote_hash: remote_metadata.content_hash,
-                    });
-                }
-                Ok(ConflictResult::NoConflict)
-            }
-            Err(e) => {
-                if e.to_string().contains("not_found") {
-                    Ok(ConflictResult::NoConflict)
-                } else {
-                    Ok(ConflictResult::Error(e.to_string()))
-                }
+    /// Compare an upload with the remote file, asking Dropbox only when the metadata cache cannot answer
+    async fn detect_conflict(&self, path: &str, local_content: &[u8], parent_rev: Option<&str>) -> Result<ConflictResult> {
+        let cached = self.metadata_cache().lookup(path);
+        let remote_metadata = match cached {
+            Cached::Absent => return Ok(ConflictResult::NoConflict),
+            Cached::Present(metadata) => {
+                debug!("Checking {} for conflicts against cached rev {}", path, metadata.rev);
+                *metadata
             }
+            Cached::Unknown => match self.get_metadata(path).await {
+                Ok(metadata) => metadata,
+                Err(e) if e.to_string().contains("not_found") => return Ok(ConflictResult::NoConflict),
+                Err(e) => return Ok(ConflictResult::Error(e.to_string())),
+            },
+        };
+
+        if parent_rev == Some(remote_metadata.rev.as_str()) {
+            return Ok(ConflictResult::NoConflict);
         }
+        let local_size = local_content.len() as u64;
+        let remote_size = remote_metadata.size;
+        if parent_rev.is_some() || local_size != remote_size {
+            return Ok(ConflictResult::Conflict {
+                local_size,
+                remote_size,
+                local_modified: None,
+                remote_modified: remote_metadata.server_modified
+                    .as_ref()
+                    .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                    .map(|dt| dt.with_timezone(&Utc)),
+                local_hash: None,
+                remote_hash: remote_metadata.content_hash,
+            });
+        }
+        Ok(ConflictResult::NoConflict)
     }
 
     pub async fn upload_files_batch(&self, files: &[(String, Vec<u8>)]) -> Result<Vec<Result<()>>> {
         let mut results = Vec::new();
         for (path, content) in files {
-            let result = self.upload_file_with_options(path, content, &UploadOptions::default()).await;
+            let result = self.upload_file_with_options(path, content, &UploadOptions::default()).await.map(|_| ());
             results.push(result);
         }
         Ok(results)
     }
 
+    fn directory_remote_path(local_dir: &Path, remote_base: &str, entry: &Path) -> Result<String> {
+        let relative = paths::remote_path(local_dir, entry)
+            .ok_or_else(|| anyhow::anyhow!("Failed to get relative path of {}", entry.display()))?;
+        Ok(format!("{}{}", remote_base, relative))
+    }
+
     pub async fn upload_directory(&self, local_dir: &Path, remote_base: &str) -> Result<()> {
         if !local_dir.exists() || !local_dir.is_dir() {
             return Err(anyhow::anyhow!("Local directory does not exist or is not a directory: {}", local_dir.display()));
         }
 
-        let mut queue = VecDeque::new();
-        queue.push_back((local_dir.to_path_buf(), remote_base.to_string()));
+        let rules = IgnoreRules::load(local_dir, &[], &[])?;
+        let remote_base = remote_base.trim_end_matches('/');
+        let mut uploaded = HashSet::new();
+        let mut skipped: Vec<PathBuf> = Vec::new();
 
-        while let Some((current_dir, current_remote_base)) = queue.pop_front() {
-            for entry in fs::read_dir(&current_dir)
-                .map_err(|e| anyhow::anyhow!("Failed to read directory {}: {}", current_dir.display(), e))? {
-                
-                let entry = entry
-                    .map_err(|e| anyhow::anyhow!("Failed to read directory entry: {}", e))?;
-                
-                let entry_path = entry.path();
-                let relative_path = entry_path.strip_prefix(local_dir)
-                    .map_err(|e| anyhow::anyhow!("Failed to get relative path: {}", e))?;
-                
-                let remote_path = format!("{}/{}", current_remote_base, relative_path.display());
+        let scanner = Scanner::new(local_dir).with_ignore(Arc::new(rules));
+        for entry in scanner.scan(local_dir).filter(|entry| entry.path != local_dir) {
+            if skipped.iter().any(|folder| entry.path.starts_with(folder)) {
+                continue;
+            }
+            if let Some(reason) = entry.path.file_name().and_then(names::check_name) {
+                warn!("Skipping {}: {}", entry.path.display(), reason);
+                skipped.push(entry.path);
+                continue;
+            }
 
-                if entry_path.is_file() {
-                    self.upload_local_file(&entry_path, &remote_path).await?;
-                } else if entry_path.is_dir() {
-                    // Add subdirectory to queue instead of recursive call
-                    queue.push_back((entry_path, remote_path));
+            match entry.kind {
+                EntryKind::File => {
+                    let mut remote_path = Self::directory_remote_path(local_dir, remote_base, &entry.path)?;
+                    if !uploaded.insert(paths::index_key(&remote_path)) {
+                        let renamed = paths::labelled_sibling(&entry.path, paths::NAME_CONFLICT_LABEL);
+                        remote_path = Self::directory_remote_path(local_dir, remote_base, &renamed)?;
+                        warn!("{} clashes with another file name on Dropbox; uploading it as {}", entry.path.display(), remote_path);
+                        uploaded.insert(paths::index_key(&remote_path));
+                    }
+                    self.upload_local_file(&entry.path, &remote_path).await?;
                 }
+                EntryKind::Dir => {
+                    // Create the folder up front so empty folders are uploaded too
+                    let remote_path = Self::directory_remote_path(local_dir, remote_base, &entry.path)?;
+                    self.create_folder(&remote_path).await?;
+                }
+                EntryKind::Symlink => debug!("Skipping symlink {}", entry.path.display()),
             }
         }
 
@@ -264,17 +318,23 @@ impl DropboxClient {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::TempDir;
-    use std::fs::File;
-    use std::io::Write;
 
     #[tokio::test]
     async fn test_upload_options_default() {
         let options = UploadOptions::default();
-        assert_eq!(options.overwrite, false);
-        assert_eq!(options.create_backup, true);
-        assert_eq!(options.autorename, false);
-        assert_eq!(options.mute, false);
+        assert!(!options.overwrite);
+        assert!(options.create_backup);
+        assert!(!options.autorename);
+        assert!(!options.mute);
+    }
+
+    #[test]
+    fn test_known_rev_uploads_as_update() {
+        let options = UploadOptions { overwrite: true, parent_rev: Some("015f".to_string()), ..UploadOptions::default() };
+        assert_eq!(options.write_mode(), serde_json::json!({ ".tag": "update", "update": "015f" }));
+        let options = UploadOptions { overwrite: true, ..UploadOptions::default() };
+        assert_eq!(options.write_mode(), serde_json::json!("overwrite"));
+        assert_eq!(UploadOptions::default().write_mode(), serde_json::json!("add"));
     }
 
     #[test]
@@ -289,8 +349,8 @@ mod tests {
             remote_hash: None,
         };
         let error = ConflictResult::Error("test error".to_string());
-        format!("{:?}", no_conflict);
-        format!("{:?}", conflict);
-        format!("{:?}", error);
+        let _ = format!("{:?}", no_conflict);
+        let _ = format!("{:?}", conflict);
+        let _ = format!("{:?}", error);
     }
 } 
\ No newline at end of file
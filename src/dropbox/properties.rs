@@ -0,0 +1,106 @@
+use crate::Result;
+use super::client::{DropboxClient, DropboxError};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+/// A named value stored in a Dropbox property group
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PropertyField {
+    pub name: String,
+    pub value: String,
+}
+
+impl PropertyField {
+    pub fn new(name: &str, value: impl Into<String>) -> Self {
+        Self { name: name.to_string(), value: value.into() }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PropertyGroup {
+    template_id: String,
+    fields: Vec<PropertyField>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetadataWithProperties {
+    #[serde(default)]
+    property_groups: Vec<PropertyGroup>,
+}
+
+impl DropboxClient {
+    /// Find the user's property template called `name`, creating it with string `fields` if missing
+    pub async fn ensure_property_template(&self, name: &str, description: &str, fields: &[&str]) -> Result<String> {
+        let listed = self.properties_call("file_properties/templates/list_for_user", &serde_json::Value::Null).await?;
+        let ids: Vec<String> = listed["template_ids"].as_array()
+            .map(|ids| ids.iter().filter_map(|id| id.as_str().map(str::to_string)).collect())
+            .unwrap_or_default();
+
+        for id in ids {
+            let template = self.properties_call(
+                "file_properties/templates/get_for_user",
+                &serde_json::json!({ "template_id": id }),
+            ).await?;
+            if template["name"] == name {
+                debug!("Using property template {} ({})", name, id);
+                return Ok(id);
+            }
+        }
+
+        let fields: Vec<_> = fields.iter()
+            .map(|field| serde_json::json!({ "name": field, "description": field, "type": "string" }))
+            .collect();
+        let created = self.properties_call(
+            "file_properties/templates/add_for_user",
+            &serde_json::json!({ "name": name, "description": description, "fields": fields }),
+        ).await?;
+        let id = created["template_id"].as_str()
+            .ok_or_else(|| anyhow::anyhow!("Property template response has no template_id"))?
+            .to_string();
+        info!("Created property template {} ({})", name, id);
+        Ok(id)
+    }
+
+    /// Replace the values of a property group on a file
+    pub async fn overwrite_properties(&self, path: &str, template_id: &str, fields: &[PropertyField]) -> Result<()> {
+        self.properties_call("file_properties/properties/overwrite", &serde_json::json!({
+            "path": path,
+            "property_groups": [{ "template_id": template_id, "fields": fields }],
+        })).await?;
+        debug!("Stored {} properties on {}", fields.len(), path);
+        Ok(())
+    }
+
+    /// Read the values of a property group from a file, empty if none are set
+    pub async fn get_properties(&self, path: &str, template_id: &str) -> Result<Vec<PropertyField>> {
+        let metadata = self.properties_call("files/get_metadata", &serde_json::json!({
+            "path": path,
+            "include_property_groups": { ".tag": "filter_some", "filter_some": [template_id] },
+        })).await?;
+        let metadata: MetadataWithProperties = serde_json::from_value(metadata)
+            .map_err(|e| anyhow::anyhow!("Failed to parse properties of {}: {}", path, e))?;
+
+        Ok(metadata.property_groups.into_iter()
+            .filter(|group| group.template_id == template_id)
+            .flat_map(|group| group.fields)
+            .collect())
+    }
+
+    async fn properties_call(&self, endpoint: &str, payload: &serde_json::Value) -> Result<serde_json::Value> {
+        let request = self.client.post(format!("{}/{}", self.base_url, endpoint));
+        let request = if payload.is_null() { request.header("Content-Type", "application/json").body("null") } else { request.json(payload) };
+        let response = request
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to call {}: {}", endpoint, e))?;
+
+        if !response.status().is_success() {
+            let error: DropboxError = response.json().await
+                .map_err(|e| anyhow::anyhow!("Failed to parse error response: {}", e))?;
+            return Err(anyhow::anyhow!("Failed to call {}: {}", endpoint, error.error_summary));
+        }
+
+        response.json().await
+            .map_err(|e| anyhow::anyhow!("Failed to parse {} response: {}", endpoint, e))
+    }
+}
@@ -1,30 +1,46 @@
 use boxdrop_sync_daemon::{Result, ConfigManager, DropboxClient, SyncEngine};
+use boxdrop_sync_daemon::utils::cli::{self, Cli, Command};
+use clap::Parser;
 use tracing::{info, error};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => run_daemon().await,
+        Command::Status => cli::show_status(),
+        Command::Deletions { action } => action.execute(),
+        Command::Conflicts { action } => action.execute(),
+        Command::Trash { action } => action.execute(),
+        Command::Selective { action } => action.execute(),
+        Command::Ignore { action } => action.execute(),
+    }
+}
+
+async fn run_daemon() -> Result<()> {
     // Initialize logging
     tracing_subscriber::fmt::init();
-    
+
     info!("Starting Dropbox Sync Daemon");
-    
+
     // Load configuration
     let config = ConfigManager::load()?;
     info!("Configuration loaded successfully");
-    
+
     // Initialize Dropbox client
     let client = DropboxClient::new(&config.dropbox_token)?;
     info!("Dropbox client initialized");
-    
+
     // Initialize sync engine
-    let sync_engine = SyncEngine::new(client, config)?;
+    let mut sync_engine = SyncEngine::new(client, config)?;
     info!("Sync engine initialized");
-    
+
     // Start the sync daemon
     if let Err(e) = sync_engine.run().await {
         error!("Sync engine failed: {}", e);
-        return Err(e.into());
+        return Err(e);
     }
-    
+
     Ok(())
-} 
\ No newline at end of file
+}
@@ -1,4 +1,5 @@
 // TODO: Implement daemon service in future task
+#[derive(Default)]
 pub struct Daemon {
     // TODO: Add daemon implementation
 }
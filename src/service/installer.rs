@@ -1,4 +1,5 @@
 // TODO: Implement service installer in future task
+#[derive(Default)]
 pub struct Installer {
     // TODO: Add installer implementation
 }
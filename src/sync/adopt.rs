@@ -0,0 +1,87 @@
+use crate::dropbox::client::{FileMetadata, RemoteEntry};
+use super::hash_cache::HashCache;
+use super::index::SyncIndex;
+use super::paths;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Outcome of comparing an already populated sync folder with the Dropbox listing
+#[derive(Debug, Default)]
+pub struct Adoption {
+    /// Local files identical to their Dropbox entry
+    pub matched: Vec<(FileMetadata, PathBuf)>,
+    /// Local files whose content differs from their Dropbox entry
+    pub differing: Vec<PathBuf>,
+}
+
+/// Match untracked local files to Dropbox entries by content hash
+///
+/// Files missing locally are not reported; the regular initial sync
+/// downloads them.
+pub fn compare(root: &Path, index: &SyncIndex, hashes: &mut HashCache, entries: &[RemoteEntry]) -> Adoption {
+    let mut adoption = Adoption::default();
+    for entry in entries {
+        let RemoteEntry::File(metadata) = entry else { continue };
+        if metadata.symlink_info.is_some() || index.get(&metadata.path_display).is_some() {
+            continue;
+        }
+        let Some(local) = paths::local_path(root, &metadata.path_display) else { continue };
+        if !std::fs::symlink_metadata(&local).is_ok_and(|m| m.is_file()) {
+            continue;
+        }
+
+        match hashes.content_hash(&local) {
+            Ok(hash) if metadata.content_hash.as_deref() == Some(hash.as_str()) => {
+                adoption.matched.push((metadata.clone(), local));
+            }
+            Ok(_) => adoption.differing.push(local),
+            Err(e) => warn!("Not adopting {}: {}", local.display(), e),
+        }
+    }
+    adoption
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dropbox::content_hash;
+    use tempfile::TempDir;
+
+    fn remote_file(path: &str, content: &[u8]) -> RemoteEntry {
+        RemoteEntry::File(FileMetadata {
+            name: path.rsplit('/').next().unwrap().to_string(),
+            path_lower: path.to_lowercase(),
+            path_display: path.to_string(),
+            id: format!("id:{}", path),
+            client_modified: None,
+            server_modified: None,
+            rev: "a1".to_string(),
+            size: content.len() as u64,
+            is_downloadable: true,
+            content_hash: Some(content_hash::content_hash(content)),
+            symlink_info: None,
+            tag: "file".to_string(),
+        })
+    }
+
+    #[test]
+    fn test_matches_by_content_hash() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("sync");
+        std::fs::create_dir_all(root.join("Photos")).unwrap();
+        std::fs::write(root.join("Photos/cat.jpg"), b"meow").unwrap();
+        std::fs::write(root.join("notes.txt"), b"edited copy").unwrap();
+        let index = SyncIndex::load(&dir.path().join("index.json")).unwrap();
+
+        let mut hashes = HashCache::load(&dir.path().join("hash_cache.json"));
+        let adoption = compare(&root, &index, &mut hashes, &[
+            remote_file("/Photos/cat.jpg", b"meow"),
+            remote_file("/notes.txt", b"original"),
+            remote_file("/missing.pdf", b"pdf"),
+        ]);
+
+        assert_eq!(adoption.matched.len(), 1);
+        assert_eq!(adoption.matched[0].1, root.join("Photos/cat.jpg"));
+        assert_eq!(adoption.differing, vec![root.join("notes.txt")]);
+    }
+}
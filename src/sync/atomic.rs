@@ -0,0 +1,184 @@
+use crate::Result;
+use crate::utils::timestamps;
+use super::index::LocalIdentity;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+/// Name prefix of the hidden temp files downloads are staged in
+pub const PARTIAL_PREFIX: &str = ".boxdrop-partial-";
+
+/// Result of an atomic replacement
+#[derive(Debug)]
+pub enum WriteOutcome {
+    /// The target now holds the new content
+    Replaced,
+    /// The target changed since the write was planned; the new content was
+    /// left untouched in the returned staged file for the caller to place
+    TargetChanged(PathBuf),
+}
+
+/// Whether a file name belongs to a staged download
+pub fn is_partial(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(PARTIAL_PREFIX))
+}
+
+/// Replace `target` with `content` without ever exposing a half-written file
+///
+/// The content is written to a hidden temp file in the same directory,
+/// fsynced, given `client_modified` as its mtime and renamed over the target.
+/// `expected` is the identity the target had when the write was planned
+/// (`None` if it should not exist); if the target no longer matches, nothing
+/// is replaced and [`WriteOutcome::TargetChanged`] is returned instead.
+pub fn replace_file(target: &Path, content: &[u8], client_modified: Option<&str>, expected: Option<LocalIdentity>) -> Result<WriteOutcome> {
+    let parent = target.parent()
+        .ok_or_else(|| anyhow::anyhow!("{} has no parent directory", target.display()))?;
+    std::fs::create_dir_all(parent)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", parent.display(), e))?;
+
+    let temp = staging_path(target);
+    if let Err(e) = write_staged(&temp, content, client_modified) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+    commit_staged(&temp, target, parent, expected)
+}
+
+/// Write a state file in one step, so a crash leaves either the old or the new content
+pub fn write_file(target: &Path, content: &[u8]) -> Result<()> {
+    let parent = target.parent()
+        .ok_or_else(|| anyhow::anyhow!("{} has no parent directory", target.display()))?;
+    std::fs::create_dir_all(parent)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", parent.display(), e))?;
+
+    let temp = staging_path(target);
+    if let Err(e) = write_staged(&temp, content, None) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+    std::fs::rename(&temp, target)
+        .map_err(|e| anyhow::anyhow!("Failed to move {} into place: {}", target.display(), e))?;
+    sync_dir(parent);
+    Ok(())
+}
+
+/// Replace `target` with a symbolic link to `link_target`, like [`replace_file`]
+pub fn replace_symlink(target: &Path, link_target: &Path, expected: Option<LocalIdentity>) -> Result<WriteOutcome> {
+    let parent = target.parent()
+        .ok_or_else(|| anyhow::anyhow!("{} has no parent directory", target.display()))?;
+    std::fs::create_dir_all(parent)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", parent.display(), e))?;
+
+    let temp = staging_path(target);
+    std::os::unix::fs::symlink(link_target, &temp)
+        .map_err(|e| anyhow::anyhow!("Failed to create link {}: {}", temp.display(), e))?;
+    commit_staged(&temp, target, parent, expected)
+}
+
+/// Rename a staged entry over `target` unless the target changed since planning
+fn commit_staged(temp: &Path, target: &Path, parent: &Path, expected: Option<LocalIdentity>) -> Result<WriteOutcome> {
+    if LocalIdentity::of(target) != expected {
+        warn!("{} changed since the download was planned", target.display());
+        return Ok(WriteOutcome::TargetChanged(temp.to_path_buf()));
+    }
+
+    std::fs::rename(temp, target)
+        .map_err(|e| anyhow::anyhow!("Failed to move {} into place: {}", target.display(), e))?;
+    sync_dir(parent);
+    debug!("Atomically replaced {}", target.display());
+    Ok(WriteOutcome::Replaced)
+}
+
+/// Move a staged file to `destination`, keeping the staged content and mtime
+pub fn place_staged(staged: &Path, destination: &Path) -> Result<()> {
+    std::fs::rename(staged, destination)
+        .map_err(|e| anyhow::anyhow!("Failed to move {} to {}: {}", staged.display(), destination.display(), e))?;
+    if let Some(parent) = destination.parent() {
+        sync_dir(parent);
+    }
+    Ok(())
+}
+
+/// Remove staged downloads left behind by an interrupted run
+pub fn cleanup_partials(root: &Path) -> Result<usize> {
+    let mut removed = 0;
+    for entry in WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_dir() && is_partial(entry.path()) {
+            std::fs::remove_file(entry.path())
+                .map_err(|e| anyhow::anyhow!("Failed to remove {}: {}", entry.path().display(), e))?;
+            removed += 1;
+        }
+    }
+    if removed > 0 {
+        info!("Removed {} interrupted downloads", removed);
+    }
+    Ok(removed)
+}
+
+fn staging_path(target: &Path) -> PathBuf {
+    let name = target.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let nonce = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    target.with_file_name(format!("{}{}.{}.{}", PARTIAL_PREFIX, name, std::process::id(), nonce))
+}
+
+fn write_staged(temp: &Path, content: &[u8], client_modified: Option<&str>) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", temp.display(), e))?;
+    file.write_all(content)
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", temp.display(), e))?;
+    file.sync_all()
+        .map_err(|e| anyhow::anyhow!("Failed to flush {}: {}", temp.display(), e))?;
+    drop(file);
+    timestamps::apply_client_modified(temp, client_modified)
+}
+
+/// Flush a directory so a completed rename survives a crash
+fn sync_dir(dir: &Path) {
+    if let Ok(handle) = File::open(dir) {
+        let _ = handle.sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_replace_sets_content_and_mtime() {
+        let dir = TempDir::new().unwrap();
+        let target = dir.path().join("notes.txt");
+        std::fs::write(&target, b"old").unwrap();
+        let expected = LocalIdentity::of(&target);
+
+        let outcome = replace_file(&target, b"new", Some("2020-01-02T03:04:05Z"), expected).unwrap();
+
+        assert!(matches!(outcome, WriteOutcome::Replaced));
+        assert_eq!(std::fs::read(&target).unwrap(), b"new");
+        let mtime: chrono::DateTime<chrono::Utc> = std::fs::metadata(&target).unwrap().modified().unwrap().into();
+        assert_eq!(mtime.to_rfc3339(), "2020-01-02T03:04:05+00:00");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_changed_target_is_left_alone() {
+        let dir = TempDir::new().unwrap();
+        let target = dir.path().join("notes.txt");
+        std::fs::write(&target, b"edited locally").unwrap();
+
+        let outcome = replace_file(&target, b"remote", None, None).unwrap();
+
+        let WriteOutcome::TargetChanged(staged) = outcome else { panic!("expected a conflict") };
+        assert_eq!(std::fs::read(&target).unwrap(), b"edited locally");
+        assert_eq!(std::fs::read(&staged).unwrap(), b"remote");
+        assert_eq!(cleanup_partials(dir.path()).unwrap(), 1);
+    }
+}
@@ -1,23 +1,1646 @@
 use crate::{Result, DropboxClient, ConfigManager};
-use tracing::info;
+use crate::config::manager::{ConflictPolicy, InitialSyncMode, SymlinkPolicy};
+use crate::conflict::backup::{Backup, BackupInfo, BackupSource};
+use crate::conflict::merge;
+use crate::conflict::queue::{ConflictQueue, ConflictReport, SettledConflict};
+use crate::conflict::resolver::{Conflict, ConflictResolver, Resolution};
+use crate::dropbox::client::{FileMetadata, FolderMetadata, RemoteEntry};
+use crate::dropbox::metadata_cache::MetadataCache;
+use crate::dropbox::operations::{FileOperations, UploadConflict, UploadOptions};
+use crate::ui::notifications::{Notifications, Urgency};
+use crate::utils::timestamps;
+use super::adopt;
+use super::atomic::{self, WriteOutcome};
+use super::hash_cache::HashCache;
+use super::ignore_rules::IgnoreRules;
+use super::index::{IndexEntry, LocalIdentity, SyncIndex};
+use super::journal::{Journal, JournalEntry, JournalOp, Stage};
+use super::names::{Unsyncable, UnsyncableLog};
+use super::offline::{Backoff, OfflineQueue};
+use super::paths;
+use super::permissions::{self, PosixAttributes};
+use super::planner::{Planner, SyncAction};
+use super::safety::{Decision, DeletionBrake, DeletionSide, HeldDeletion, PendingDeletions};
+use super::selective::{SelectiveState, SelectiveSync};
+use super::watcher::{LocalChange, LocalWatcher};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, error, info, warn};
+
+/// How long the watcher waits for related events before handing over a batch
+const WATCH_SETTLE: Duration = Duration::from_millis(500);
+
+/// How often Dropbox is asked for changes since the last cursor
+const REMOTE_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Actions that failed while the rest of their batch went ahead
+#[derive(Debug, Default, thiserror::Error)]
+#[error("{count} sync actions failed, the first with: {first}")]
+struct BatchFailed {
+    count: usize,
+    first: String,
+    /// Dropbox paths the failed actions touched
+    remotes: Vec<String>,
+}
+
+impl BatchFailed {
+    fn add(&mut self, remotes: Vec<String>, error: anyhow::Error) {
+        warn!("Failed to sync {}: {}", remotes.join(", "), error);
+        if self.count == 0 {
+            self.first = error.to_string();
+        }
+        self.count += 1;
+        self.remotes.extend(remotes);
+    }
+
+    /// Whether a failed action touched `remote`, something below it or a folder above it
+    fn touches(&self, remote: &str) -> bool {
+        let key = paths::index_key(remote);
+        self.remotes.iter()
+            .map(|failed| paths::index_key(failed))
+            .any(|failed| paths::is_within(&failed, &key) || paths::is_within(&key, &failed))
+    }
+
+    fn into_result(self) -> Result<()> {
+        match self.count {
+            0 => Ok(()),
+            _ => Err(self.into()),
+        }
+    }
+}
 
 /// Core synchronization engine
 pub struct SyncEngine {
     client: DropboxClient,
     config: ConfigManager,
+    index: SyncIndex,
+    notifications: Notifications,
+    pending_deletions_path: PathBuf,
+    conflicts_path: PathBuf,
+    conflict_report_path: PathBuf,
+    trash: Backup,
+    /// Versions replaced while settling conflicts, shared with the client
+    conflict_backups: Arc<Backup>,
+    resolver: ConflictResolver,
+    selective: SelectiveSync,
+    selective_state_path: PathBuf,
+    ignore: Arc<IgnoreRules>,
+    unsyncable: UnsyncableLog,
+    unsyncable_path: PathBuf,
+    /// Id of the property template for POSIX attributes, registered on first use
+    permissions_template: Option<String>,
+    offline: OfflineQueue,
+    offline_path: PathBuf,
+    backoff: Backoff,
+    /// When to check again whether Dropbox is reachable while offline
+    next_probe: Instant,
+    journal: Journal,
+    /// Local content hashes, shared with the planner
+    hashes: Mutex<HashCache>,
+    /// Journal entries left by an interrupted run that still need checking
+    unrecovered: Vec<JournalEntry>,
+    /// Local changes whose actions failed, retried on the next remote poll
+    retry_local: Vec<LocalChange>,
 }
 
 impl SyncEngine {
     /// Create a new sync engine
     pub fn new(client: DropboxClient, config: ConfigManager) -> Result<Self> {
         info!("Initializing sync engine");
-        Ok(Self { client, config })
+        let index = SyncIndex::load(&SyncIndex::default_path()?)?;
+        let (journal, unrecovered) = Journal::open(&Journal::default_path()?)?;
+        let conflict_backups = Arc::new(Backup::conflicts(&config));
+        let offline = OfflineQueue::load(&OfflineQueue::default_path()?)?;
+        // Changes still queued while online failed to replay after the last reconnect
+        let retry_local = if offline.is_offline() { Vec::new() } else { offline.pending() };
+        Ok(Self {
+            client: client
+                .with_metadata_cache(MetadataCache::load(&MetadataCache::default_path()?))
+                .with_backup_store(Arc::clone(&conflict_backups)),
+            index,
+            notifications: Notifications::new(),
+            pending_deletions_path: PendingDeletions::default_path()?,
+            conflicts_path: ConflictQueue::default_path()?,
+            conflict_report_path: ConflictReport::default_path()?,
+            trash: Backup::trash(&config),
+            conflict_backups,
+            resolver: ConflictResolver::new(&config.conflicts)?,
+            selective: SelectiveSync::new(&config.selective_sync.excluded_folders),
+            selective_state_path: SelectiveState::default_path()?,
+            ignore: Arc::new(IgnoreRules::load(&config.sync_folder, &config.ignore_patterns, &config.stores())?),
+            unsyncable: UnsyncableLog::load(&UnsyncableLog::default_path()?)?,
+            unsyncable_path: UnsyncableLog::default_path()?,
+            permissions_template: None,
+            offline,
+            offline_path: OfflineQueue::default_path()?,
+            backoff: Backoff::default(),
+            next_probe: Instant::now(),
+            journal,
+            hashes: Mutex::new(HashCache::load(&HashCache::default_path()?)),
+            unrecovered,
+            retry_local,
+            config,
+        })
     }
-    
+
     /// Run the sync engine
-    pub async fn run(&self) -> Result<()> {
+    pub async fn run(&mut self) -> Result<()> {
         info!("Starting sync engine");
-        // TODO: Implement sync logic in future tasks
+        let root = self.config.sync_folder.clone();
+        std::fs::create_dir_all(&root)
+            .map_err(|e| anyhow::anyhow!("Failed to create sync folder {}: {}", root.display(), e))?;
+
+        atomic::cleanup_partials(&root)?;
+        if let Err(e) = self.trash.prune() {
+            warn!("Failed to prune trash: {}", e);
+        }
+        let queued_backups = ConflictQueue::load(&self.conflicts_path).map(|queue| queue.backups());
+        if let Err(e) = queued_backups.and_then(|kept| self.conflict_backups.prune_except(&kept)) {
+            warn!("Failed to prune conflict backups: {}", e);
+        }
+        self.recover().await?;
+
+        if self.index.cursor().is_none() {
+            self.initial_sync().await?;
+        }
+        if let Err(e) = self.review_selective_sync().await {
+            if !self.lost_connection().await {
+                return Err(e);
+            }
+        }
+
+        let mut watcher = LocalWatcher::new(&root)?;
+        let mut remote_poll = tokio::time::interval(REMOTE_POLL_INTERVAL);
+
+        loop {
+            tokio::select! {
+                changes = watcher.next_changes(WATCH_SETTLE) => {
+                    let Some(changes) = changes else { break };
+                    if changes.iter().any(|change| IgnoreRules::is_ignore_file(change.path())) {
+                        Arc::make_mut(&mut self.ignore).reload_files();
+                    }
+                    let marked = changes.iter()
+                        .filter_map(|change| self.ignore.marked_ancestor(change.path()))
+                        .filter_map(|path| paths::remote_path(&root, &path))
+                        .filter(|remote| self.index.get(remote).is_some() || self.index.contains_within(remote))
+                        .collect();
+                    if let Err(e) = self.hold_ignored(marked) {
+                        error!("Failed to hold ignored paths: {}", e);
+                    }
+                    if self.unsyncable.prune() {
+                        if let Err(e) = self.unsyncable.save(&self.unsyncable_path) {
+                            error!("Failed to save unsyncable paths: {}", e);
+                        }
+                    }
+                    let changes = self.filter_local(changes);
+                    self.sync_local(changes).await;
+                }
+                _ = remote_poll.tick(), if !self.offline.is_offline() => {
+                    self.poll_remote().await;
+                }
+                _ = tokio::time::sleep_until(self.next_probe), if self.offline.is_offline() => {
+                    self.probe_connection().await;
+                }
+                _ = tokio::signal::ctrl_c() => {
+                    info!("Stopping sync engine");
+                    break;
+                }
+            }
+        }
+
+        self.index.save()
+    }
+
+    /// Propagate local changes, queueing them while Dropbox is unreachable
+    ///
+    /// Changes whose actions fail for another reason are kept and retried on
+    /// the next remote poll.
+    async fn sync_local(&mut self, changes: Vec<LocalChange>) {
+        if self.offline.is_offline() {
+            self.queue_offline(changes);
+            return;
+        }
+        let actions = self.planner().plan_local(&changes);
+        if let Err(e) = self.apply(actions).await {
+            error!("Failed to sync local changes: {}", e);
+            if self.lost_connection().await {
+                self.queue_offline(changes);
+                return;
+            }
+            let failed = match e.downcast_ref::<BatchFailed>() {
+                Some(failed) => self.failed_changes(changes, failed),
+                None => changes,
+            };
+            self.retry_later(failed);
+        }
+    }
+
+    /// Keep local changes for the retry on the next remote poll, skipping repeats
+    fn retry_later(&mut self, changes: Vec<LocalChange>) {
+        for change in changes {
+            if !self.retry_local.contains(&change) {
+                self.retry_local.push(change);
+            }
+        }
+    }
+
+    /// The changes a failed batch did not fully propagate
+    fn failed_changes(&self, changes: Vec<LocalChange>, failed: &BatchFailed) -> Vec<LocalChange> {
+        let root = &self.config.sync_folder;
+        let touched = |path: &Path| paths::remote_path(root, path).is_some_and(|remote| failed.touches(&remote));
+        changes.into_iter()
+            .filter(|change| match change {
+                LocalChange::Moved { from, to } => touched(from) || touched(to),
+                change => touched(change.path()),
+            })
+            .collect()
+    }
+
+    /// Run the periodic remote steps, stopping early if the connection is lost
+    async fn poll_remote(&mut self) {
+        self.reload_selective_sync();
+        if let Err(e) = self.review_selective_sync().await {
+            error!("Failed to apply selective sync changes: {}", e);
+            if self.lost_connection().await {
+                return;
+            }
+        }
+        if let Err(e) = self.review_pending_deletions().await {
+            error!("Failed to process held deletions: {}", e);
+            if self.lost_connection().await {
+                return;
+            }
+        }
+        if let Err(e) = self.review_conflicts().await {
+            error!("Failed to settle queued conflicts: {}", e);
+            if self.lost_connection().await {
+                return;
+            }
+        }
+        if let Err(e) = self.sync_remote().await {
+            error!("Failed to sync remote changes: {}", e);
+            if self.lost_connection().await {
+                return;
+            }
+        }
+        let retry = std::mem::take(&mut self.retry_local);
+        if !retry.is_empty() {
+            info!("Retrying {} local changes that failed to sync", retry.len());
+            self.sync_local(retry).await;
+            if !self.offline.is_offline() && self.offline.pending_paths() > 0 {
+                self.offline.reconnected(self.retry_local.clone());
+                if let Err(e) = self.offline.save(&self.offline_path) {
+                    error!("Failed to save offline queue: {}", e);
+                }
+            }
+        }
+    }
+
+    /// After a failure, check whether Dropbox is unreachable and switch to offline mode if so
+    async fn lost_connection(&mut self) -> bool {
+        if self.client.is_reachable().await {
+            return false;
+        }
+        if !self.offline.is_offline() {
+            warn!("Dropbox is unreachable; queueing local changes until it is back");
+            self.offline.go_offline();
+            self.backoff.reset();
+        }
+        self.offline.push(std::mem::take(&mut self.retry_local));
+        self.next_probe = Instant::now() + self.backoff.next_delay();
+        if let Err(e) = self.offline.save(&self.offline_path) {
+            error!("Failed to save offline queue: {}", e);
+        }
+        true
+    }
+
+    fn queue_offline(&mut self, changes: Vec<LocalChange>) {
+        self.offline.push(changes);
+        debug!("{} paths waiting for the connection", self.offline.pending_paths());
+        if let Err(e) = self.offline.save(&self.offline_path) {
+            error!("Failed to save offline queue: {}", e);
+        }
+    }
+
+    /// While offline, check whether Dropbox is back and replay the queued changes
+    ///
+    /// Remote changes are fetched first, so the queued local changes are
+    /// planned against the current Dropbox state and edits made on both sides
+    /// end up as conflicted copies rather than overwrites.
+    async fn probe_connection(&mut self) {
+        if !self.client.is_reachable().await {
+            let delay = self.backoff.next_delay();
+            debug!("Dropbox still unreachable; probing again in {:?}", delay);
+            self.next_probe = Instant::now() + delay;
+            return;
+        }
+
+        let queued = self.offline.pending();
+        info!("Dropbox is reachable again; replaying {} queued changes", queued.len());
+        let failed = match self.catch_up(queued.clone()).await {
+            Ok(()) => Vec::new(),
+            Err(e) => {
+                error!("Failed to replay queued changes: {}", e);
+                if self.lost_connection().await {
+                    return;
+                }
+                match e.downcast_ref::<BatchFailed>() {
+                    Some(failed) => self.failed_changes(queued, failed),
+                    None => queued,
+                }
+            }
+        };
+
+        if !failed.is_empty() {
+            warn!("Keeping {} queued changes that failed to replay; retrying them on the next poll", failed.len());
+        }
+        self.offline.reconnected(failed.clone());
+        self.retry_later(failed);
+        self.backoff.reset();
+        if let Err(e) = self.offline.save(&self.offline_path) {
+            error!("Failed to save offline queue: {}", e);
+        }
+    }
+
+    /// Finish interrupted actions, apply remote changes, then the queued local ones
+    async fn catch_up(&mut self, queued: Vec<LocalChange>) -> Result<()> {
+        self.recover().await?;
+        if !self.unrecovered.is_empty() {
+            return Err(anyhow::anyhow!("Dropbox became unreachable during recovery"));
+        }
+        self.sync_remote().await?;
+        let changes = self.filter_local(queued);
+        let actions = self.planner().plan_local(&changes);
+        self.apply(actions).await
+    }
+
+    /// Check actions left in the journal by an interrupted run against the actual state
+    ///
+    /// Actions whose effect is already visible on both sides are rolled
+    /// forward into the index, the rest are replayed or left to the next
+    /// sync. Actions that never started are planned again from the local
+    /// change behind them on the next remote poll. If Dropbox becomes unreachable, the remaining entries are kept
+    /// for when it is back.
+    async fn recover(&mut self) -> Result<()> {
+        if self.unrecovered.is_empty() {
+            return Ok(());
+        }
+        info!("Checking {} actions interrupted by the last shutdown", self.unrecovered.len());
+
+        while let Some(entry) = self.unrecovered.first().cloned() {
+            if let Err(e) = self.recover_entry(&entry).await {
+                warn!("Failed to recover {:?}: {}", entry.op, e);
+                if self.lost_connection().await {
+                    return Ok(());
+                }
+            }
+            self.journal.advance(entry.seq, Stage::Done)?;
+            self.unrecovered.remove(0);
+        }
+
+        if let Err(e) = self.hashes().save() {
+            warn!("Failed to save hash cache: {}", e);
+        }
+        self.index.save()?;
+        self.journal.clear()
+    }
+
+    async fn recover_entry(&mut self, entry: &JournalEntry) -> Result<()> {
+        debug!("Recovering {:?} ({:?})", entry.op, entry.stage);
+        if entry.stage == Stage::Intent {
+            if let Some(change) = entry.op.local_change(&self.config.sync_folder) {
+                self.retry_later(vec![change]);
+            }
+            return Ok(());
+        }
+        match &entry.op {
+            JournalOp::Upload { local, remote } => {
+                let Ok(local_hash) = self.hashes().content_hash(local) else {
+                    debug!("{} no longer exists; nothing to upload", local.display());
+                    return Ok(());
+                };
+                match self.client.get_entry(remote).await? {
+                    Some(RemoteEntry::File(metadata)) if metadata.content_hash.as_deref() == Some(local_hash.as_str()) => {
+                        self.index.insert(IndexEntry::from_file(&metadata, LocalIdentity::of(local)));
+                    }
+                    _ => self.upload(local, remote).await?,
+                }
+            }
+            JournalOp::Download { remote, local } => {
+                let Some(RemoteEntry::File(metadata)) = self.client.get_entry(remote).await? else {
+                    debug!("{} no longer exists on Dropbox; leaving it to the next sync", remote);
+                    return Ok(());
+                };
+                let local_hash = self.hashes().content_hash(local).ok();
+                if local_hash.is_some() && local_hash == metadata.content_hash {
+                    self.index.insert(IndexEntry::from_file(&metadata, LocalIdentity::of(local)));
+                } else {
+                    let expected = self.index.get(remote).and_then(|known| known.local);
+                    self.download(&metadata, local, expected).await?;
+                }
+            }
+            JournalOp::CreateFolder { local, remote } => {
+                if local.is_dir() {
+                    let metadata = self.client.create_folder(remote).await?;
+                    self.index.insert(IndexEntry::from_folder(&metadata, LocalIdentity::of(local)));
+                }
+            }
+            JournalOp::MoveRemote { from, to } => {
+                match (self.client.get_entry(from).await?, self.client.get_entry(to).await?) {
+                    (None, Some(moved)) => {
+                        self.index.rename(from, moved.path_display());
+                        let local = self.local_path(moved.path_display())?;
+                        self.record_moved(&moved, &local);
+                    }
+                    (Some(_), None) => {
+                        if let Some((_, reason)) = self.move_remote(vec![(from.clone(), to.clone())]).await?.pop() {
+                            return Err(anyhow::anyhow!("Failed to move {} to {} on Dropbox: {}", from, to, reason));
+                        }
+                    }
+                    _ => debug!("Leaving the move of {} to {} to the next sync", from, to),
+                }
+            }
+            JournalOp::MoveLocal { from, to, from_remote, to_remote } => {
+                // An unfinished rename is listed again by the next remote sync
+                if !from.exists() && to.exists() {
+                    self.index.rename(from_remote, to_remote);
+                }
+            }
+            JournalOp::DeleteRemote { remote, local, rev } => {
+                let local = match local {
+                    Some(local) => local.clone(),
+                    None => self.local_path(remote)?,
+                };
+                if std::fs::symlink_metadata(&local).is_ok() {
+                    debug!("{} exists locally again; leaving it to the next sync", local.display());
+                    return Ok(());
+                }
+                if let (Some(rev), Some(RemoteEntry::File(metadata))) = (rev, self.client.get_entry(remote).await?) {
+                    if metadata.rev != *rev {
+                        info!("{} changed on Dropbox since its deletion was planned; leaving it to the next sync", remote);
+                        return Ok(());
+                    }
+                }
+                self.delete_remote(remote).await?
+            }
+            JournalOp::DeleteLocal { local, remote } => self.delete_local(local, remote)?,
+        }
+        Ok(())
+    }
+
+    /// List the whole Dropbox and bring down everything not yet present locally
+    async fn initial_sync(&mut self) -> Result<()> {
+        info!("Performing initial sync");
+        let mut delta = self.client.list_folder_recursive("").await?;
+        self.filter_remote(&mut delta.entries)?;
+        let copies = match self.config.initial_sync {
+            InitialSyncMode::Adopt => self.adopt(&delta.entries)?,
+            InitialSyncMode::Download => Vec::new(),
+        };
+        let actions = self.planner().plan_remote(&delta.entries);
+        self.apply(actions).await?;
+        let actions = self.planner().plan_local(&copies);
+        self.apply(actions).await?;
+        stamp_folders(&self.config.sync_folder, &delta.entries);
+        self.index.set_cursor(delta.cursor);
+        self.save_state()
+    }
+
+    /// Planner configured with this machine's local rules
+    fn planner(&self) -> Planner<'_> {
+        Planner::new(&self.config.sync_folder, &self.index)
+            .with_ignore(&self.ignore)
+            .escaping_names(self.config.escape_unsupported_names)
+            .with_symlinks(self.config.symlinks)
+            .with_hashes(&self.hashes)
+    }
+
+    fn hashes(&self) -> std::sync::MutexGuard<'_, HashCache> {
+        self.hashes.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Local location of a Dropbox path, refusing one that maps outside the sync folder
+    fn local_path(&self, remote: &str) -> Result<PathBuf> {
+        paths::local_path(&self.config.sync_folder, remote)
+            .ok_or_else(|| anyhow::anyhow!("Refusing to sync {}: it maps outside the sync folder", remote))
+    }
+
+    /// Whether a local path belongs to the daemon itself, such as the trash, a backup or a staged download
+    fn is_internal(&self, path: &Path) -> bool {
+        path.starts_with(self.trash.root())
+            || path.starts_with(self.conflict_backups.root())
+            || atomic::is_partial(path)
+    }
+
+    /// Whether a local path takes part in syncing
+    fn is_synced_local(&self, path: &Path) -> bool {
+        !self.is_internal(path)
+            && !self.ignore.is_ignored(path, path.is_dir())
+            && paths::remote_path(&self.config.sync_folder, path)
+                .is_some_and(|remote| !self.selective.is_excluded(&remote))
+    }
+
+    /// Drop local changes to paths that are not synced
+    ///
+    /// A move into an unsynced path counts as a removal and a move out of one
+    /// as a creation.
+    fn filter_local(&self, changes: Vec<LocalChange>) -> Vec<LocalChange> {
+        changes.into_iter()
+            .filter_map(|change| match change {
+                LocalChange::Moved { from, to } => match (self.is_synced_local(&from), self.is_synced_local(&to)) {
+                    (true, true) => Some(LocalChange::Moved { from, to }),
+                    (true, false) => Some(LocalChange::Removed(from)),
+                    (false, true) => Some(LocalChange::Created(to)),
+                    (false, false) => None,
+                },
+                change => self.is_synced_local(change.path()).then_some(change),
+            })
+            .collect()
+    }
+
+    /// Drop remote entries that are not synced to this machine
+    ///
+    /// Entries in excluded folders, on the daemon's own local directories or
+    /// below a local path marked with the ignored attribute are dropped. Marked
+    /// paths that still exist on Dropbox are held for removal there.
+    fn filter_remote(&mut self, entries: &mut Vec<RemoteEntry>) -> Result<()> {
+        let root = self.config.sync_folder.clone();
+        let mut marked = Vec::new();
+        entries.retain(|entry| {
+            let Some(local) = paths::local_path(&root, entry.path_display()) else {
+                warn!("Skipping {}: it maps outside the sync folder", entry.path_display());
+                return false;
+            };
+            if self.selective.is_excluded(entry.path_display()) || self.is_internal(&local) {
+                return false;
+            }
+            let Some(ancestor) = self.ignore.marked_ancestor(&local) else { return true };
+            if !matches!(entry, RemoteEntry::Deleted(_)) {
+                marked.extend(paths::remote_path(&root, &ancestor));
+            }
+            false
+        });
+        self.hold_ignored(marked)
+    }
+
+    /// Hold the Dropbox copies of paths marked with the ignored attribute for removal
+    fn hold_ignored(&mut self, mut remotes: Vec<String>) -> Result<()> {
+        remotes.sort();
+        remotes.dedup();
+        if remotes.is_empty() {
+            return Ok(());
+        }
+
+        let mut pending = PendingDeletions::load(&self.pending_deletions_path)?;
+        let before = pending.deletions.len();
+        pending.hold(remotes.into_iter().map(|remote| HeldDeletion { side: DeletionSide::Remote, remote }));
+        if pending.deletions.len() == before {
+            return Ok(());
+        }
+
+        warn!("Holding Dropbox copies of {} ignored paths for removal", pending.deletions.len() - before);
+        pending.save(&self.pending_deletions_path)?;
+        self.notifications.notify(
+            Urgency::Normal,
+            "Ignored files are still on Dropbox",
+            "Run `boxdrop-sync-daemon deletions list` to review, then approve to remove them from Dropbox.",
+        );
+        Ok(())
+    }
+
+    /// Pick up selective sync changes made in the configuration file while running
+    fn reload_selective_sync(&mut self) {
+        match ConfigManager::load() {
+            Ok(latest) => {
+                self.config.selective_sync = latest.selective_sync.clone();
+                self.selective = SelectiveSync::new(&self.config.selective_sync.excluded_folders);
+            }
+            Err(e) => warn!("Failed to reload selective sync settings: {}", e),
+        }
+    }
+
+    /// Act on folders excluded or re-included since the last cycle
+    ///
+    /// Re-included folders are downloaded again, and one that fails to download
+    /// is retried on the next cycle. Local copies of newly excluded
+    /// folders stay in place until removal is confirmed from the CLI.
+    async fn review_selective_sync(&mut self) -> Result<()> {
+        let mut state = SelectiveState::load(&self.selective_state_path)?;
+
+        let mut failed = Vec::new();
+        let mut error = None;
+        for folder in state.included_since(&self.selective) {
+            info!("{} is synced again, downloading it", folder);
+            if let Err(e) = self.download_subtree(&folder).await {
+                warn!("Failed to download re-included {}: {}", folder, e);
+                failed.push(folder);
+                error.get_or_insert(e);
+            }
+        }
+
+        let selective = &self.selective;
+        state.pending_removal.retain(|folder| selective.is_excluded(folder));
+        if state.confirmed {
+            for folder in std::mem::take(&mut state.pending_removal) {
+                self.remove_excluded(&folder)?;
+            }
+            self.index.save()?;
+        }
+        state.confirmed = false;
+
+        let newly_excluded: Vec<String> = state.excluded_since(&self.selective).into_iter()
+            .filter(|folder| self.index.contains_within(folder))
+            .collect();
+        if !newly_excluded.is_empty() {
+            warn!("Keeping local copies of newly excluded {:?} until confirmed", newly_excluded);
+            self.notifications.notify(
+                Urgency::Normal,
+                "Selective sync needs confirmation",
+                &format!(
+                    "{} excluded folders still have local copies. Run `boxdrop-sync-daemon selective confirm` to remove them.",
+                    newly_excluded.len()
+                ),
+            );
+            state.pending_removal.extend(newly_excluded);
+        }
+
+        // Folders that failed to download stay applied, so they are retried next cycle
+        state.applied = self.selective.excluded().to_vec();
+        state.applied.extend(failed);
+        state.save(&self.selective_state_path)?;
+        error.map_or(Ok(()), Err)
+    }
+
+    /// Take over files already in the sync folder that match Dropbox
+    ///
+    /// Identical files are recorded in the index so they are not downloaded
+    /// again. Differing local files are renamed to conflicted copies so the
+    /// initial sync downloads the Dropbox version in their place; the copies
+    /// are returned for upload.
+    fn adopt(&mut self, entries: &[RemoteEntry]) -> Result<Vec<LocalChange>> {
+        let root = self.config.sync_folder.clone();
+        info!("Comparing existing files in {} with Dropbox", root.display());
+        let hashes = self.hashes.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let adoption = adopt::compare(&root, &self.index, hashes, entries);
+
+        for (metadata, local) in &adoption.matched {
+            self.index.insert(IndexEntry::from_file(metadata, LocalIdentity::of(local)));
+        }
+        let mut copies = Vec::new();
+        for local in &adoption.differing {
+            let copy = self.resolver.copy_path(local);
+            std::fs::rename(local, &copy)
+                .map_err(|e| anyhow::anyhow!("Failed to rename {} to {}: {}", local.display(), copy.display(), e))?;
+            info!("{} differs from Dropbox; kept it as {}", local.display(), copy.display());
+            copies.push(LocalChange::Created(copy));
+        }
+
+        info!("Adopted {} existing files; {} differing files will be downloaded and their local versions uploaded as conflicted copies",
+              adoption.matched.len(), adoption.differing.len());
+        let mut hashes = self.hashes();
+        hashes.prune(adoption.matched.into_iter().map(|(_, local)| local));
+        hashes.save()?;
+        drop(hashes);
+        self.index.save()?;
+        Ok(copies)
+    }
+
+    /// List a Dropbox folder and bring down whatever is missing locally
+    async fn download_subtree(&mut self, folder: &str) -> Result<()> {
+        let mut delta = self.client.list_folder_recursive(folder).await?;
+        self.filter_remote(&mut delta.entries)?;
+        let actions = self.planner().plan_remote(&delta.entries);
+        self.apply(actions).await
+    }
+
+    /// Remove the local copy of an excluded folder, leaving Dropbox untouched
+    ///
+    /// Files changed since the last sync are kept so no local edit is lost.
+    fn remove_excluded(&mut self, folder: &str) -> Result<()> {
+        let root = self.config.sync_folder.clone();
+        let removed = self.index.remove_within(folder);
+
+        for entry in removed.iter().filter(|entry| !entry.is_dir) {
+            let Some(path) = paths::local_path(&root, &entry.path_display) else { continue };
+            if !path.exists() {
+                continue;
+            }
+            if LocalIdentity::of(&path) != entry.local {
+                warn!("Keeping {}: it changed since the last sync", path.display());
+                continue;
+            }
+            std::fs::remove_file(&path)
+                .map_err(|e| anyhow::anyhow!("Failed to remove {}: {}", path.display(), e))?;
+        }
+
+        remove_empty_folders(removed.iter()
+            .filter(|entry| entry.is_dir)
+            .filter_map(|entry| paths::local_path(&root, &entry.path_display))
+            .collect());
+        info!("Removed the local copy of excluded {}", folder);
+        Ok(())
+    }
+
+    /// Fetch and apply Dropbox changes recorded since the last cursor
+    async fn sync_remote(&mut self) -> Result<()> {
+        let Some(cursor) = self.index.cursor().map(str::to_string) else {
+            return self.initial_sync().await;
+        };
+
+        let mut delta = self.client.list_folder_continue(&cursor).await?;
+        self.filter_remote(&mut delta.entries)?;
+        if !delta.entries.is_empty() {
+            let actions = self.planner().plan_remote(&delta.entries);
+            self.apply(actions).await?;
+        }
+        self.index.set_cursor(delta.cursor);
+        self.save_state()
+    }
+
+    /// Save the index after a listing, together with the remote metadata it fed
+    fn save_state(&self) -> Result<()> {
+        if let Err(e) = self.client.save_metadata_cache() {
+            warn!("Failed to save remote metadata cache: {}", e);
+        }
+        let mut hashes = self.hashes();
+        hashes.retain_identities(self.index.entries().filter_map(|entry| entry.local));
+        if let Err(e) = hashes.save() {
+            warn!("Failed to save hash cache: {}", e);
+        }
+        drop(hashes);
+        self.index.save()
+    }
+
+    /// Execute planned actions and record their outcome in the index
+    ///
+    /// Actions on paths with a queued conflict are held back. Deletions pass
+    /// through the mass-delete brake and may be held for confirmation while
+    /// the remaining actions go ahead.
+    async fn apply(&mut self, actions: Vec<SyncAction>) -> Result<()> {
+        let actions = self.hold_conflicted(actions)?;
+        let actions = self.brake_deletions(actions)?;
+        self.execute(actions).await
+    }
+
+    /// Hold back this cycle's deletions if they exceed the safety limits
+    ///
+    /// While earlier deletions await a decision, every new deletion is held too.
+    fn brake_deletions(&mut self, actions: Vec<SyncAction>) -> Result<Vec<SyncAction>> {
+        let held: Vec<HeldDeletion> = actions.iter().filter_map(HeldDeletion::from_action).collect();
+        if held.is_empty() {
+            return Ok(actions);
+        }
+
+        let mut pending = PendingDeletions::load(&self.pending_deletions_path)?;
+        let affected = DeletionBrake::affected_files(&self.index, &held);
+        let tracked = self.index.entries().filter(|entry| !entry.is_dir).count();
+        let trips = DeletionBrake::new(&self.config.deletion_safety).trips(affected, tracked);
+
+        if pending.is_empty() && !trips {
+            return Ok(actions);
+        }
+
+        warn!("Holding {} deletions ({} files) for confirmation", held.len(), affected);
+        let newly_tripped = pending.is_empty();
+        pending.hold(held);
+        pending.save(&self.pending_deletions_path)?;
+        if newly_tripped {
+            self.notifications.notify(
+                Urgency::Critical,
+                "Sync paused deletions",
+                &format!(
+                    "{} of {} files would be deleted. Run `boxdrop-sync-daemon deletions list` to review, then approve or reject.",
+                    affected, tracked
+                ),
+            );
+        }
+        Ok(actions.into_iter().filter(|action| !action.is_deletion()).collect())
+    }
+
+    /// Apply or undo held deletions once the user has decided
+    async fn review_pending_deletions(&mut self) -> Result<()> {
+        let mut pending = PendingDeletions::load(&self.pending_deletions_path)?;
+        let Some(decision) = pending.decision else { return Ok(()) };
+        let held = std::mem::take(&mut pending.deletions);
+
+        match decision {
+            Decision::Approve => {
+                info!("Applying {} approved deletions", held.len());
+                let root = self.config.sync_folder.clone();
+                self.execute(held.iter().filter_map(|deletion| deletion.to_action(&root)).collect()).await?;
+            }
+            Decision::Reject => {
+                info!("Restoring {} rejected deletions", held.len());
+                for deletion in &held {
+                    self.restore(deletion).await?;
+                }
+                self.index.save()?;
+            }
+        }
+
+        PendingDeletions::default().save(&self.pending_deletions_path)
+    }
+
+    /// Bring back the side a rejected deletion already removed
+    async fn restore(&mut self, deletion: &HeldDeletion) -> Result<()> {
+        let files: Vec<IndexEntry> = self.index.entries_within(&deletion.remote)
+            .filter(|entry| !entry.is_dir)
+            .cloned()
+            .collect();
+
+        for entry in files {
+            let local = self.local_path(&entry.path_display)?;
+            match deletion.side {
+                DeletionSide::Remote if !local.exists() => {
+                    let metadata = self.client.get_metadata(&entry.path_display).await?;
+                    self.download(&metadata, &local, None).await?;
+                }
+                DeletionSide::Local if local.exists() => {
+                    self.upload(&local, &entry.path_display).await?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute actions without consulting the deletion brake
+    ///
+    /// Every action is journaled before it runs, and finished entries are
+    /// cleared once the index reflecting the whole batch has been saved. A
+    /// failed action does not stop the rest of the batch; the failures are
+    /// returned together as a [`BatchFailed`] error at the end, and their
+    /// journal entries are kept.
+    async fn execute(&mut self, actions: Vec<SyncAction>) -> Result<()> {
+        if actions.is_empty() {
+            return Ok(());
+        }
+        debug!("Applying {} sync actions", actions.len());
+
+        let root = self.config.sync_folder.clone();
+        let mut journaled = Vec::with_capacity(actions.len());
+        for action in actions {
+            let seq = match JournalOp::from_action(&root, &self.index, &action) {
+                Some(op) => Some(self.journal.record(op)?),
+                None => None,
+            };
+            journaled.push((action, seq));
+        }
+
+        let trashing = self.config.trash.enabled
+            && journaled.iter().any(|(action, _)| matches!(action, SyncAction::DeleteLocal { .. }));
+        let mut failed = BatchFailed::default();
+        let mut remote_moves = Vec::new();
+        for (action, seq) in journaled {
+            match action {
+                SyncAction::MoveRemote { from, to } => remote_moves.push((from, to, seq)),
+                other => {
+                    self.journaled_moves(std::mem::take(&mut remote_moves), &mut failed).await?;
+                    let remotes = other.remote_paths(&root);
+                    self.advance(seq, Stage::InProgress)?;
+                    match self.apply_one(other).await {
+                        Ok(()) => self.advance(seq, Stage::Done)?,
+                        Err(e) => failed.add(remotes, e),
+                    }
+                }
+            }
+        }
+        self.journaled_moves(remote_moves, &mut failed).await?;
+
+        if trashing {
+            if let Err(e) = self.trash.prune() {
+                warn!("Failed to prune trash: {}", e);
+            }
+        }
+        self.index.save()?;
+        self.journal.clear()?;
+        failed.into_result()
+    }
+
+    /// Run a batch of remote moves, keeping their journal entries up to date
+    async fn journaled_moves(&mut self, moves: Vec<(String, String, Option<u64>)>, failed: &mut BatchFailed) -> Result<()> {
+        if moves.is_empty() {
+            return Ok(());
+        }
+        let (pairs, seqs): (Vec<(String, String)>, Vec<Option<u64>>) = moves.into_iter()
+            .map(|(from, to, seq)| ((from, to), seq))
+            .unzip();
+        for seq in &seqs {
+            self.advance(*seq, Stage::InProgress)?;
+        }
+        match self.move_remote(pairs.clone()).await {
+            Ok(refused) => {
+                for (pair, seq) in pairs.into_iter().zip(seqs) {
+                    match refused.iter().find(|(moved, _)| *moved == pair) {
+                        Some((_, reason)) => failed.add(vec![pair.0, pair.1], anyhow::anyhow!("{}", reason)),
+                        None => self.advance(seq, Stage::Done)?,
+                    }
+                }
+            }
+            Err(e) => {
+                let remotes = pairs.into_iter().flat_map(|(from, to)| [from, to]).collect();
+                failed.add(remotes, e);
+            }
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self, seq: Option<u64>, stage: Stage) -> Result<()> {
+        match seq {
+            Some(seq) => self.journal.advance(seq, stage),
+            None => Ok(()),
+        }
+    }
+
+    async fn apply_one(&mut self, action: SyncAction) -> Result<()> {
+        match action {
+            SyncAction::Upload { local, remote } => self.upload(&local, &remote).await?,
+            SyncAction::Download { metadata, local, expected } => self.download(&metadata, &local, expected).await?,
+            SyncAction::MoveLocal { from, to, entry } => self.move_local(&from, &to, entry).await?,
+            SyncAction::RenameLocal { from, to } => self.rename_local(&from, &to)?,
+            SyncAction::Unsyncable { local, reason } => self.record_unsyncable(&local, &reason)?,
+            SyncAction::TrackFolder { metadata, local } => self.track_folder(&metadata, &local),
+            SyncAction::CreateFolder { local, remote } => {
+                let metadata = self.client.create_folder(&remote).await?;
+                self.index.insert(IndexEntry::from_folder(&metadata, LocalIdentity::of(&local)));
+                info!("Created folder {} on Dropbox", metadata.path_display);
+            }
+            SyncAction::MoveRemote { from, to } => {
+                if let Some((_, reason)) = self.move_remote(vec![(from.clone(), to.clone())]).await?.pop() {
+                    return Err(anyhow::anyhow!("Failed to move {} to {} on Dropbox: {}", from, to, reason));
+                }
+            }
+            SyncAction::DeleteRemote { remote } => self.delete_remote(&remote).await?,
+            SyncAction::DeleteLocal { local, remote } => self.delete_local(&local, &remote)?,
+        }
+        Ok(())
+    }
+
+    /// Make sure a Dropbox folder exists locally and record it in the index
+    fn track_folder(&mut self, metadata: &FolderMetadata, local: &Path) {
+        if !local.exists() {
+            match std::fs::create_dir_all(local) {
+                Ok(()) => debug!("Created folder {}", local.display()),
+                Err(e) => warn!("Failed to create folder {}: {}", local.display(), e),
+            }
+        } else if !local.is_dir() {
+            warn!("{} is a folder on Dropbox but not locally; leaving it untouched", local.display());
+            return;
+        }
+        self.index.insert(IndexEntry::from_folder(metadata, LocalIdentity::of(local)));
+    }
+
+    /// Delete a Dropbox entry whose local copy was removed
+    ///
+    /// Nothing is deleted if the local copy has come back since. Files edited
+    /// or added on Dropbox after the last sync are downloaded again instead of
+    /// deleted, so a local deletion never erases edits made elsewhere; the
+    /// unchanged files next to them are still deleted.
+    async fn delete_remote(&mut self, remote: &str) -> Result<()> {
+        let local = self.local_path(remote)?;
+        if std::fs::symlink_metadata(&local).is_ok() {
+            info!("{} exists locally again; not deleting it on Dropbox", local.display());
+            return Ok(());
+        }
+
+        let files = match self.client.get_entry(remote).await? {
+            None | Some(RemoteEntry::Deleted(_)) => {
+                self.index.remove_within(remote);
+                return Ok(());
+            }
+            Some(RemoteEntry::File(metadata)) => vec![metadata],
+            Some(RemoteEntry::Folder(_)) => self.client.list_folder_recursive(remote).await?.entries.into_iter()
+                .filter_map(|entry| match entry {
+                    RemoteEntry::File(metadata) => Some(metadata),
+                    _ => None,
+                })
+                .collect(),
+        };
+        let (unchanged, changed): (Vec<FileMetadata>, Vec<FileMetadata>) = files.into_iter()
+            .partition(|metadata| self.index.get(&metadata.path_display).is_some_and(|known| {
+                known.rev.as_deref() == Some(metadata.rev.as_str())
+                    || (known.content_hash.is_some() && known.content_hash == metadata.content_hash)
+            }));
+
+        if changed.is_empty() {
+            let rev = self.index.get(remote).filter(|known| !known.is_dir).and_then(|known| known.rev.clone());
+            self.client.delete_entry(remote, rev.as_deref()).await?;
+            self.index.remove_within(remote);
+            info!("Deleted {} on Dropbox", remote);
+            return Ok(());
+        }
+
+        warn!("{} files in {} changed on Dropbox since they were deleted here; keeping them", changed.len(), remote);
+        for metadata in unchanged {
+            self.client.delete_entry(&metadata.path_display, Some(&metadata.rev)).await?;
+            self.index.remove(&metadata.path_display);
+        }
+        for metadata in changed {
+            let local = self.local_path(&metadata.path_display)?;
+            self.index.remove(&metadata.path_display);
+            self.download(&metadata, &local, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Remove the local copies of a deleted Dropbox entry
+    ///
+    /// Only files that still match the index are removed, and folders are
+    /// removed only once empty, so untracked or edited local data survives.
+    /// Removed files go to the local trash unless it is disabled.
+    fn delete_local(&mut self, local: &Path, remote: &str) -> Result<()> {
+        let root = self.config.sync_folder.clone();
+        let removed = self.index.remove_within(remote);
+
+        for entry in removed.iter().filter(|entry| !entry.is_dir) {
+            let Some(path) = paths::local_path(&root, &entry.path_display) else { continue };
+            if entry.local.is_some() && LocalIdentity::of(&path) != entry.local {
+                warn!("Keeping {}: it changed since the last sync", path.display());
+                continue;
+            }
+            if !path.exists() {
+                continue;
+            }
+            if self.config.trash.enabled {
+                let relative = path.strip_prefix(&root).unwrap_or(&path);
+                let stored = self.trash.preserve(&path, relative)?;
+                info!("Moved deleted {} to {}", path.display(), stored.display());
+            } else {
+                std::fs::remove_file(&path)
+                    .map_err(|e| anyhow::anyhow!("Failed to delete {}: {}", path.display(), e))?;
+                info!("Deleted {}", path.display());
+            }
+        }
+
+        remove_empty_folders(removed.iter()
+            .filter(|entry| entry.is_dir)
+            .filter_map(|entry| paths::local_path(&root, &entry.path_display))
+            .chain(local.is_dir().then(|| local.to_path_buf()))
+            .collect());
+        Ok(())
+    }
+
+    /// Upload a local file, or the target of a link when links are stored
+    async fn upload(&mut self, local: &Path, remote: &str) -> Result<()> {
+        let content = if self.config.symlinks == SymlinkPolicy::Store && local.is_symlink() {
+            std::fs::read_link(local)
+                .map(|target| target.into_os_string().into_vec())
+                .map_err(|e| anyhow::anyhow!("Failed to read link {}: {}", local.display(), e))?
+        } else {
+            std::fs::read(local)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", local.display(), e))?
+        };
+        let options = if let Some(entry) = self.index.get(remote) {
+            UploadOptions { overwrite: true, create_backup: false, parent_rev: entry.rev.clone(), ..UploadOptions::default() }
+        } else {
+            UploadOptions::default()
+        };
+        let metadata = match self.client.upload_file_with_options(remote, &content, &options).await {
+            Ok(metadata) => metadata,
+            Err(e) => return match e.downcast::<UploadConflict>() {
+                Ok(conflict) => self.upload_conflict(local, remote, conflict.parent_rev).await,
+                Err(e) => Err(e),
+            },
+        };
+        self.index.insert(IndexEntry::from_file(&metadata, LocalIdentity::of(local)));
+
+        if self.config.permissions.enabled && !local.is_symlink() {
+            if let Err(e) = self.store_permissions(local, &metadata.path_display).await {
+                warn!("Failed to store permissions of {}: {}", local.display(), e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Settle an upload Dropbox refused because the file changed there since `base_rev`
+    async fn upload_conflict(&mut self, local: &Path, remote: &str, base_rev: String) -> Result<()> {
+        let remote_metadata = match self.client.get_entry(remote).await? {
+            Some(RemoteEntry::File(metadata)) => Some(metadata),
+            _ => None,
+        };
+        let mut conflict = self.conflict(local, remote, remote_metadata);
+        conflict.base_rev = Some(base_rev);
+        self.settle_conflict(conflict, None).await
+    }
+
+    /// Describe a conflict between a local edit and the given Dropbox version
+    fn conflict(&self, local: &Path, remote: &str, remote_metadata: Option<FileMetadata>) -> Conflict {
+        let metadata = std::fs::metadata(local).ok();
+        Conflict {
+            local: local.to_path_buf(),
+            remote: remote.to_string(),
+            base_rev: self.index.get(remote).and_then(|entry| entry.rev.clone()),
+            local_size: metadata.as_ref().map_or(0, |m| m.len()),
+            local_modified: metadata.and_then(|m| m.modified().ok()).map(DateTime::<Utc>::from),
+            remote_metadata,
+        }
+    }
+
+    /// Apply the resolver's decision to a conflict
+    async fn settle_conflict(&mut self, conflict: Conflict, staged: Option<PathBuf>) -> Result<()> {
+        let resolution = self.resolver.resolve(&conflict);
+        self.apply_resolution(resolution, conflict, staged).await
+    }
+
+    /// Settle a conflict the chosen way; `staged` holds the already downloaded Dropbox version, if any
+    async fn apply_resolution(&mut self, resolution: Resolution, conflict: Conflict, staged: Option<PathBuf>) -> Result<()> {
+        debug!("Settling conflict on {} with {:?}", conflict.remote, resolution);
+        let root = self.config.sync_folder.clone();
+        let relative = conflict.local.strip_prefix(&root).unwrap_or(&conflict.local).to_path_buf();
+
+        match resolution {
+            Resolution::KeepBoth => self.keep_both(conflict, staged).await,
+            Resolution::KeepLocal => {
+                info!("Keeping the local version of {}", conflict.remote);
+                match &conflict.remote_metadata {
+                    Some(metadata) => {
+                        let info = BackupInfo {
+                            source: Some(BackupSource::Dropbox),
+                            rev: Some(metadata.rev.clone()),
+                            content_hash: metadata.content_hash.clone(),
+                        };
+                        let stored = match staged {
+                            Some(staged) => self.conflict_backups.preserve_with(&staged, &relative, info)?,
+                            None => {
+                                let content = self.client.download_file(&metadata.path_display).await?;
+                                self.conflict_backups.store(&content, &relative, info)?
+                            }
+                        };
+                        debug!("Kept the replaced Dropbox version of {} as {}", conflict.remote, stored.display());
+                        self.index.insert(IndexEntry::from_file(metadata, None));
+                    }
+                    None => {
+                        self.index.remove(&conflict.remote);
+                    }
+                }
+                Box::pin(self.upload(&conflict.local, &conflict.remote)).await
+            }
+            Resolution::KeepRemote => {
+                if conflict.local.exists() {
+                    let info = BackupInfo { source: Some(BackupSource::Local), ..BackupInfo::default() };
+                    let stored = self.conflict_backups.preserve_with(&conflict.local, &relative, info)?;
+                    info!("Keeping the Dropbox version of {}; moved the local edit to {}", conflict.remote, stored.display());
+                }
+                self.take_remote(&conflict, staged).await
+            }
+            Resolution::Merge => self.merge_conflict(conflict, &relative, staged).await,
+            Resolution::Ask => self.queue_conflict(conflict, &relative, staged).await,
+        }
+    }
+
+    /// Three-way merge a text file against the rev both sides last agreed on
+    ///
+    /// Binary files, edits touching the same lines and conflicts without a
+    /// known base fall back to keeping both versions.
+    async fn merge_conflict(&mut self, conflict: Conflict, relative: &Path, staged: Option<PathBuf>) -> Result<()> {
+        let (Some(base_rev), Some(metadata)) = (conflict.base_rev.clone(), conflict.remote_metadata.clone()) else {
+            debug!("No base revision to merge {} against", conflict.remote);
+            return self.keep_both(conflict, staged).await;
+        };
+        let expected = LocalIdentity::of(&conflict.local);
+        let ours = std::fs::read(&conflict.local)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", conflict.local.display(), e))?;
+        let theirs = match &staged {
+            Some(staged) => std::fs::read(staged)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", staged.display(), e))?,
+            None => self.client.download_file(&metadata.path_display).await?,
+        };
+        if !merge::is_text(&ours) || !merge::is_text(&theirs) {
+            debug!("{} is not a text file; keeping both versions", conflict.remote);
+            return self.keep_both(conflict, staged).await;
+        }
+        let base = match self.client.download_revision(&base_rev).await {
+            Ok(base) if merge::is_text(&base) => base,
+            Ok(_) => return self.keep_both(conflict, staged).await,
+            Err(e) => {
+                warn!("Failed to fetch the base of {} for merging: {}", conflict.remote, e);
+                return self.keep_both(conflict, staged).await;
+            }
+        };
+
+        let text = |content: &[u8]| String::from_utf8_lossy(content).into_owned();
+        let Some(merged) = merge::three_way(&text(&base), &text(&ours), &text(&theirs)) else {
+            info!("Edits to {} overlap; keeping both versions", conflict.remote);
+            return self.keep_both(conflict, staged).await;
+        };
+
+        let info = BackupInfo { source: Some(BackupSource::Local), ..BackupInfo::default() };
+        self.conflict_backups.store(&ours, relative, info)?;
+        let info = BackupInfo {
+            source: Some(BackupSource::Dropbox),
+            rev: Some(metadata.rev.clone()),
+            content_hash: metadata.content_hash.clone(),
+        };
+        self.conflict_backups.store(&theirs, relative, info)?;
+
+        if let Some(staged) = &staged {
+            let _ = std::fs::remove_file(staged);
+        }
+        if let WriteOutcome::TargetChanged(unused) = atomic::replace_file(&conflict.local, merged.as_bytes(), None, expected)? {
+            let _ = std::fs::remove_file(&unused);
+            return Err(anyhow::anyhow!("{} changed again while merging; it is retried on the next sync", conflict.local.display()));
+        }
+        info!("Merged the local and Dropbox edits of {}", conflict.remote);
+        self.index.insert(IndexEntry::from_file(&metadata, None));
+        Box::pin(self.upload(&conflict.local, &conflict.remote)).await
+    }
+
+    /// Back up both versions and leave the conflict for the user, holding the path meanwhile
+    async fn queue_conflict(&mut self, conflict: Conflict, relative: &Path, staged: Option<PathBuf>) -> Result<()> {
+        let mut backups = Vec::new();
+        let content = std::fs::read(&conflict.local)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", conflict.local.display(), e))?;
+        let info = BackupInfo { source: Some(BackupSource::Local), ..BackupInfo::default() };
+        backups.push(self.conflict_backups.store(&content, relative, info)?);
+
+        if let Some(metadata) = &conflict.remote_metadata {
+            let info = BackupInfo {
+                source: Some(BackupSource::Dropbox),
+                rev: Some(metadata.rev.clone()),
+                content_hash: metadata.content_hash.clone(),
+            };
+            backups.push(match staged {
+                Some(staged) => self.conflict_backups.preserve_with(&staged, relative, info)?,
+                None => {
+                    let content = self.client.download_file(&metadata.path_display).await?;
+                    self.conflict_backups.store(&content, relative, info)?
+                }
+            });
+        }
+
+        let mut queue = ConflictQueue::load(&self.conflicts_path)?;
+        queue.push(conflict.clone(), backups);
+        queue.save(&self.conflicts_path)?;
+        warn!("{} changed both here and on Dropbox; holding it until you decide", conflict.remote);
+        self.notifications.notify(
+            Urgency::Normal,
+            "Sync conflict needs a decision",
+            &format!("{} was changed on Dropbox and here. Run `boxdrop-sync-daemon conflicts list` to review.", conflict.local.display()),
+        );
         Ok(())
     }
-} 
\ No newline at end of file
+
+    /// Apply the decisions the user made on queued conflicts
+    ///
+    /// Each conflict is settled against the current state of both sides, which
+    /// may have changed while it was waiting, and the outcome is added to the
+    /// conflict report. A conflict that fails to settle loses its decision so
+    /// it is not retried every cycle.
+    async fn review_conflicts(&mut self) -> Result<()> {
+        let queue = ConflictQueue::load(&self.conflicts_path)?;
+        let decided: Vec<(String, DateTime<Utc>, ConflictPolicy)> = queue.conflicts.iter()
+            .filter_map(|queued| queued.decision
+                .map(|policy| (queued.conflict.remote.clone(), queued.detected_at, policy)))
+            .filter(|(_, _, policy)| *policy != ConflictPolicy::Ask)
+            .collect();
+        if decided.is_empty() {
+            return Ok(());
+        }
+
+        let mut report = ConflictReport::load(&self.conflict_report_path)?;
+        for (remote, detected_at, policy) in decided {
+            let (resolution, outcome) = self.settle_queued(&remote, policy).await;
+            if let Err(e) = &outcome {
+                warn!("Failed to settle the conflict on {}: {}", remote, e);
+            }
+            // Settling may queue a new conflict on the path, so work on the queue as saved now
+            let mut queue = ConflictQueue::load(&self.conflicts_path)?;
+            queue.finish(&remote, detected_at, outcome.is_ok());
+            queue.save(&self.conflicts_path)?;
+            report.record(SettledConflict {
+                settled_at: Utc::now(),
+                remote,
+                decision: policy,
+                resolution: resolution.map_or_else(|| "-".to_string(), |resolution| format!("{:?}", resolution)),
+                error: outcome.err().map(|e| e.to_string()),
+            });
+            report.save(&self.conflict_report_path)?;
+        }
+        self.index.save()
+    }
+
+    /// Settle one queued conflict the way the user decided
+    async fn settle_queued(&mut self, remote: &str, policy: ConflictPolicy) -> (Option<Resolution>, Result<()>) {
+        let remote_metadata = match self.client.get_entry(remote).await {
+            Ok(Some(RemoteEntry::File(metadata))) => Some(metadata),
+            Ok(_) => None,
+            Err(e) => return (None, Err(e)),
+        };
+        let local = match self.local_path(remote) {
+            Ok(local) => local,
+            Err(e) => return (None, Err(e)),
+        };
+        let conflict = self.conflict(&local, remote, remote_metadata);
+        let resolution = ConflictResolver::apply_policy(policy, &conflict);
+        info!("Settling queued conflict on {} as decided: {:?}", remote, resolution);
+        (Some(resolution), self.apply_resolution(resolution, conflict, None).await)
+    }
+
+    /// Drop actions on paths with an unsettled conflict; they are synced once it is settled
+    fn hold_conflicted(&self, actions: Vec<SyncAction>) -> Result<Vec<SyncAction>> {
+        let queue = ConflictQueue::load(&self.conflicts_path)?;
+        if queue.is_empty() {
+            return Ok(actions);
+        }
+        let root = &self.config.sync_folder;
+        Ok(actions.into_iter()
+            .filter(|action| {
+                let held = action.remote_paths(root).iter().any(|remote| queue.blocks(remote));
+                if held {
+                    debug!("Holding {:?} until its conflict is settled", action);
+                }
+                !held
+            })
+            .collect())
+    }
+
+    /// Move the local edit to a conflicted copy next to it and bring down the Dropbox version
+    async fn keep_both(&mut self, conflict: Conflict, staged: Option<PathBuf>) -> Result<()> {
+        if conflict.remote_metadata.is_none() {
+            debug!("{} was deleted on Dropbox; uploading the local edit as a new file", conflict.remote);
+            self.index.remove(&conflict.remote);
+            return Box::pin(self.upload(&conflict.local, &conflict.remote)).await;
+        }
+
+        let copy = self.resolver.copy_path(&conflict.local);
+        let copy_remote = paths::remote_path(&self.config.sync_folder, &copy)
+            .ok_or_else(|| anyhow::anyhow!("{} is outside the sync folder", copy.display()))?;
+        std::fs::rename(&conflict.local, &copy)
+            .map_err(|e| anyhow::anyhow!("Failed to rename {} to {}: {}", conflict.local.display(), copy.display(), e))?;
+        warn!("{} changed on Dropbox while edited locally; saved the local version as {}",
+              conflict.remote, copy.display());
+        self.notifications.notify(
+            Urgency::Normal,
+            "Sync conflict",
+            &format!("{} was changed on Dropbox and here; your version was saved as {}", conflict.local.display(), copy.display()),
+        );
+
+        Box::pin(self.upload(&copy, &copy_remote)).await?;
+        self.take_remote(&conflict, staged).await
+    }
+
+    /// Put the Dropbox version of a conflicting file in place, or forget it if deleted there
+    async fn take_remote(&mut self, conflict: &Conflict, staged: Option<PathBuf>) -> Result<()> {
+        let Some(metadata) = &conflict.remote_metadata else {
+            self.index.remove(&conflict.remote);
+            return Ok(());
+        };
+        match staged {
+            Some(staged) => {
+                atomic::place_staged(&staged, &conflict.local)?;
+                self.finish_download(metadata, &conflict.local).await;
+                Ok(())
+            }
+            None => Box::pin(self.download(metadata, &conflict.local, None)).await,
+        }
+    }
+
+    /// Id of the POSIX attributes property template, creating it on first use
+    async fn permissions_template(&mut self) -> Result<String> {
+        if let Some(id) = &self.permissions_template {
+            return Ok(id.clone());
+        }
+        let id = self.client.ensure_property_template(
+            permissions::TEMPLATE_NAME,
+            permissions::TEMPLATE_DESCRIPTION,
+            permissions::TEMPLATE_FIELDS,
+        ).await?;
+        self.permissions_template = Some(id.clone());
+        Ok(id)
+    }
+
+    /// Attach the mode (and owner names if configured) of a local file to its Dropbox copy
+    async fn store_permissions(&mut self, local: &Path, remote: &str) -> Result<()> {
+        let Some(attributes) = PosixAttributes::read(local, self.config.permissions.owners) else {
+            return Ok(());
+        };
+        let template = self.permissions_template().await?;
+        self.client.overwrite_properties(remote, &template, &attributes.to_fields()).await
+    }
+
+    /// Apply attributes stored on a Dropbox file to its local copy
+    ///
+    /// Files uploaded from machines without POSIX attributes keep the default mode.
+    async fn restore_permissions(&mut self, local: &Path, remote: &str) -> Result<()> {
+        let template = self.permissions_template().await?;
+        let fields = self.client.get_properties(remote, &template).await?;
+        match PosixAttributes::from_fields(&fields) {
+            Some(attributes) => attributes.apply(local, self.config.permissions.owners),
+            None => {
+                debug!("No permissions stored for {}", remote);
+                Ok(())
+            }
+        }
+    }
+
+    /// Write a Dropbox file to its local path with its original timestamp
+    ///
+    /// The file is replaced atomically. If the local file changed since the
+    /// download was planned, the conflict is settled by the resolver.
+    async fn download(&mut self, metadata: &FileMetadata, local: &Path, expected: Option<LocalIdentity>) -> Result<()> {
+        let outcome = match &metadata.symlink_info {
+            Some(link) => atomic::replace_symlink(local, Path::new(&link.target), expected)?,
+            None => {
+                let content = self.client.download_file(&metadata.path_display).await?;
+                atomic::replace_file(local, &content, metadata.client_modified.as_deref(), expected)?
+            }
+        };
+
+        let WriteOutcome::TargetChanged(staged) = outcome else {
+            self.finish_download(metadata, local).await;
+            return Ok(());
+        };
+
+        // Only the timestamps changed if the content still matches the last sync
+        let known_hash = self.index.get(&metadata.path_display).and_then(|known| known.content_hash.clone());
+        let untouched = known_hash.is_some() && self.hashes().content_hash(local).ok() == known_hash;
+        if !local.exists() || untouched {
+            atomic::place_staged(&staged, local)?;
+            self.finish_download(metadata, local).await;
+            return Ok(());
+        }
+
+        let conflict = self.conflict(local, &metadata.path_display, Some(metadata.clone()));
+        self.settle_conflict(conflict, Some(staged)).await
+    }
+
+    /// Record a file that now matches its Dropbox version, restoring its permissions if enabled
+    async fn finish_download(&mut self, metadata: &FileMetadata, local: &Path) {
+        if self.config.permissions.enabled && metadata.symlink_info.is_none() {
+            if let Err(e) = self.restore_permissions(local, &metadata.path_display).await {
+                warn!("Failed to restore permissions of {}: {}", local.display(), e);
+            }
+        }
+        self.index.insert(IndexEntry::from_file(metadata, LocalIdentity::of(local)));
+    }
+
+    /// Rename a local entry to follow a move made on Dropbox
+    ///
+    /// Anything already at the new path is kept as a conflicted copy.
+    async fn move_local(&mut self, from: &Path, to: &Path, entry: RemoteEntry) -> Result<()> {
+        let root = &self.config.sync_folder;
+        let old_remote = paths::remote_path(root, from)
+            .ok_or_else(|| anyhow::anyhow!("{} is outside the sync folder", from.display()))?;
+
+        if !from.exists() {
+            debug!("{} is missing locally, fetching {} instead", from.display(), to.display());
+            self.index.remove(&old_remote);
+            if let RemoteEntry::File(metadata) = &entry {
+                self.download(metadata, to, None).await?;
+            }
+            return Ok(());
+        }
+
+        if std::fs::symlink_metadata(to).is_ok() {
+            self.set_aside(to, entry.path_display()).await?;
+        }
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        std::fs::rename(from, to)
+            .map_err(|e| anyhow::anyhow!("Failed to rename {} to {}: {}", from.display(), to.display(), e))?;
+        info!("Moved {} to {}", from.display(), to.display());
+
+        self.index.rename(&old_remote, entry.path_display());
+        self.record_moved(&entry, to);
+        Ok(())
+    }
+
+    /// Move a local entry in the way of a Dropbox move to a conflicted copy and upload it
+    async fn set_aside(&mut self, local: &Path, remote: &str) -> Result<()> {
+        let copy = self.resolver.copy_path(local);
+        std::fs::rename(local, &copy)
+            .map_err(|e| anyhow::anyhow!("Failed to rename {} to {}: {}", local.display(), copy.display(), e))?;
+        warn!("{} was in the way of a move on Dropbox; saved it as {}", local.display(), copy.display());
+        self.notifications.notify(
+            Urgency::Normal,
+            "Sync conflict",
+            &format!("A file moved on Dropbox took the place of {}; the local one was saved as {}", local.display(), copy.display()),
+        );
+
+        // Whatever was indexed here is replaced by the moved entry
+        self.index.remove(remote);
+        let actions = self.planner().plan_local(&[LocalChange::Created(copy)]);
+        for action in actions {
+            Box::pin(self.apply_one(action)).await?;
+        }
+        Ok(())
+    }
+
+    /// Rename a local file whose name clashes with another once on Dropbox
+    fn rename_local(&mut self, from: &Path, to: &Path) -> Result<()> {
+        if std::fs::symlink_metadata(to).is_ok() {
+            warn!("Not renaming {} over existing {}", from.display(), to.display());
+            return Ok(());
+        }
+        std::fs::rename(from, to)
+            .map_err(|e| anyhow::anyhow!("Failed to rename {} to {}: {}", from.display(), to.display(), e))?;
+
+        warn!("Renamed {} to {} because Dropbox would treat both names as the same file", from.display(), to.display());
+        self.notifications.notify(
+            Urgency::Normal,
+            "Renamed a conflicting file",
+            &format!("{} clashed with another file name and was renamed to {}", from.display(), to.display()),
+        );
+        Ok(())
+    }
+
+    /// Remember a local file Dropbox would refuse and tell the user once
+    fn record_unsyncable(&mut self, local: &Path, reason: &Unsyncable) -> Result<()> {
+        if !self.unsyncable.record(local, reason) {
+            return Ok(());
+        }
+        warn!("Not syncing {}: {}", local.display(), reason);
+        self.notifications.notify(
+            Urgency::Normal,
+            "File cannot be synced",
+            &format!("{}: {}", local.display(), reason),
+        );
+        self.unsyncable.save(&self.unsyncable_path)
+    }
+
+    /// Relocate Dropbox entries to follow local moves, batching when there are several
+    ///
+    /// Returns the moves Dropbox refused with its reason. Their index entries
+    /// keep the old path, so the move is planned again when retried.
+    async fn move_remote(&mut self, moves: Vec<(String, String)>) -> Result<Vec<((String, String), String)>> {
+        let results = match moves.len() {
+            0 => return Ok(Vec::new()),
+            1 => {
+                let (from, to) = &moves[0];
+                vec![Ok(self.client.move_entry(from, to).await?)]
+            }
+            _ => self.client.move_batch(&moves).await?,
+        };
+
+        let mut refused = Vec::new();
+        for ((from, to), result) in moves.into_iter().zip(results) {
+            match result {
+                Ok(entry) => {
+                    info!("Moved {} to {} on Dropbox", from, to);
+                    self.index.rename(&from, entry.path_display());
+                    if let Some(local) = paths::local_path(&self.config.sync_folder, entry.path_display()) {
+                        self.record_moved(&entry, &local);
+                    }
+                }
+                Err(reason) => refused.push(((from, to), reason)),
+            }
+        }
+        Ok(refused)
+    }
+
+    /// Refresh the index entry of a moved file or folder with its new metadata
+    fn record_moved(&mut self, entry: &RemoteEntry, local: &Path) {
+        let identity = LocalIdentity::of(local);
+        match entry {
+            RemoteEntry::File(metadata) => {
+                self.index.insert(IndexEntry::from_file(metadata, identity));
+            }
+            RemoteEntry::Folder(metadata) => {
+                self.index.insert(IndexEntry::from_folder(metadata, identity));
+            }
+            RemoteEntry::Deleted(_) => {}
+        }
+    }
+}
+
+/// Give each local folder the newest `client_modified` of the files below it
+///
+/// Dropbox keeps no folder timestamps, so without this every folder would
+/// carry the time of the initial sync.
+fn stamp_folders(root: &Path, entries: &[RemoteEntry]) {
+    let mut newest: HashMap<PathBuf, DateTime<Utc>> = HashMap::new();
+    for entry in entries {
+        let RemoteEntry::File(metadata) = entry else { continue };
+        let Some(modified) = metadata.client_modified.as_deref().and_then(timestamps::parse_dropbox_time) else {
+            continue;
+        };
+        let Some(local) = paths::local_path(root, &metadata.path_display) else { continue };
+        for folder in local.ancestors().skip(1).take_while(|folder| *folder != root && folder.starts_with(root)) {
+            let time = newest.entry(folder.to_path_buf()).or_insert(modified);
+            *time = (*time).max(modified);
+        }
+    }
+
+    for (folder, modified) in newest {
+        if let Err(e) = timestamps::set_mtime(&folder, modified) {
+            debug!("Keeping the timestamp of {}: {}", folder.display(), e);
+        }
+    }
+}
+
+/// Remove the given folders deepest first, skipping any that are not empty
+fn remove_empty_folders(mut folders: Vec<PathBuf>) {
+    folders.sort_by_key(|path| std::cmp::Reverse(path.components().count()));
+    for folder in folders {
+        if std::fs::remove_dir(&folder).is_ok() {
+            debug!("Removed empty folder {}", folder.display());
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{ConfigManager, Result};
+use crate::dropbox::content_hash;
+use super::atomic;
+use super::index::LocalIdentity;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// What identifies unchanged file content without reading it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct CacheKey {
+    device: u64,
+    inode: u64,
+    size: u64,
+    mtime_ns: i64,
+}
+
+impl CacheKey {
+    fn of(metadata: &Metadata) -> Self {
+        Self {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+            size: metadata.size(),
+            mtime_ns: nanos(metadata.mtime(), metadata.mtime_nsec()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedHash {
+    #[serde(flatten)]
+    key: CacheKey,
+    /// Change time when hashed, catching in-place edits that restore the mtime
+    ctime_ns: i64,
+    content_hash: String,
+}
+
+/// Dropbox content hashes of local files, so a rescan costs one stat per file
+///
+/// Entries are keyed by device, inode, size and mtime and are only trusted
+/// while the ctime is also unchanged.
+#[derive(Debug, Default)]
+pub struct HashCache {
+    path: PathBuf,
+    entries: HashMap<CacheKey, CachedHash>,
+    dirty: bool,
+}
+
+impl HashCache {
+    /// Location of the hash cache inside the data directory
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(ConfigManager::data_dir()?.join("hash_cache.json"))
+    }
+
+    /// Load the cache, starting empty if none was saved or it cannot be read
+    pub fn load(path: &Path) -> Self {
+        let entries: Vec<CachedHash> = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        debug!("Loaded {} cached hashes", entries.len());
+        Self {
+            path: path.to_path_buf(),
+            entries: entries.into_iter().map(|entry| (entry.key, entry)).collect(),
+            dirty: false,
+        }
+    }
+
+    /// Save the cache if it changed since it was loaded
+    pub fn save(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let entries: Vec<&CachedHash> = self.entries.values().collect();
+        let json = serde_json::to_string(&entries)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize hash cache: {}", e))?;
+        atomic::write_file(&self.path, json.as_bytes())?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Dropbox content hash of a local file, read from the cache when still valid
+    pub fn content_hash(&mut self, path: &Path) -> Result<String> {
+        let metadata = std::fs::metadata(path)
+            .map_err(|e| anyhow::anyhow!("Failed to stat {}: {}", path.display(), e))?;
+        let key = CacheKey::of(&metadata);
+        let ctime_ns = nanos(metadata.ctime(), metadata.ctime_nsec());
+        if let Some(cached) = self.entries.get(&key).filter(|cached| cached.ctime_ns == ctime_ns) {
+            return Ok(cached.content_hash.clone());
+        }
+
+        let hash = content_hash::file_content_hash(path)?;
+        // A file written to while it was read may not match the hash; leave it uncached
+        let after = std::fs::metadata(path)
+            .map_err(|e| anyhow::anyhow!("Failed to stat {}: {}", path.display(), e))?;
+        if CacheKey::of(&after) == key && nanos(after.ctime(), after.ctime_nsec()) == ctime_ns {
+            self.entries.insert(key, CachedHash { key, ctime_ns, content_hash: hash.clone() });
+            self.dirty = true;
+        }
+        Ok(hash)
+    }
+
+    /// Drop entries for inodes that no longer hold the cached content
+    pub fn prune(&mut self, live: impl IntoIterator<Item = PathBuf>) {
+        let live: std::collections::HashSet<CacheKey> = live.into_iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|metadata| CacheKey::of(&metadata))
+            .collect();
+        let before = self.entries.len();
+        self.entries.retain(|key, _| live.contains(key));
+        self.dirty |= self.entries.len() != before;
+    }
+
+    /// Drop entries that do not match the recorded identity of a synced file
+    ///
+    /// Keeps the cache to about one entry per file in the sync index without
+    /// touching the disk; hashes of edits not yet synced are simply read again.
+    pub fn retain_identities(&mut self, live: impl IntoIterator<Item = LocalIdentity>) {
+        let live: std::collections::HashSet<(u64, u64, u64, i64)> = live.into_iter()
+            .map(|identity| (identity.device, identity.inode, identity.size, identity.mtime))
+            .collect();
+        let before = self.entries.len();
+        self.entries.retain(|key, _| {
+            live.contains(&(key.device, key.inode, key.size, key.mtime_ns.div_euclid(1_000_000_000)))
+        });
+        self.dirty |= self.entries.len() != before;
+    }
+}
+
+fn nanos(seconds: i64, nanoseconds: i64) -> i64 {
+    seconds.saturating_mul(1_000_000_000).saturating_add(nanoseconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_in_place_edit_with_restored_mtime_is_rehashed() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("doc.txt");
+        std::fs::write(&file, b"first").unwrap();
+        let mut cache = HashCache::load(&dir.path().join("hash_cache.json"));
+
+        let first = cache.content_hash(&file).unwrap();
+        assert_eq!(first, content_hash::content_hash(b"first"));
+        cache.save().unwrap();
+
+        let mtime = std::fs::metadata(&file).unwrap().modified().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        std::fs::write(&file, b"other").unwrap();
+        std::fs::File::options().write(true).open(&file).unwrap().set_modified(mtime).unwrap();
+
+        let mut reloaded = HashCache::load(&dir.path().join("hash_cache.json"));
+        assert_eq!(reloaded.entries.len(), 1);
+        assert_eq!(reloaded.content_hash(&file).unwrap(), content_hash::content_hash(b"other"));
+
+        // Only the hash of the content the index knows survives
+        std::fs::write(&file, b"longer content").unwrap();
+        reloaded.content_hash(&file).unwrap();
+        assert_eq!(reloaded.entries.len(), 2);
+        reloaded.retain_identities(LocalIdentity::of(&file));
+        assert_eq!(reloaded.entries.len(), 1);
+        reloaded.retain_identities(None);
+        assert!(reloaded.entries.is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+use crate::Result;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+use walkdir::WalkDir;
+
+/// Name of the per-folder ignore file, using gitignore syntax
+pub const IGNORE_FILE: &str = ".boxdropignore";
+
+/// Patterns that are always ignored: lock and swap files plus staged downloads
+///
+/// The daemon's trash and conflict backup folders are added from their
+/// configured locations, see [`IgnoreRules::load`].
+pub const BUILTIN_PATTERNS: &[&str] = &[
+    ".~lock*",
+    "*.swp",
+    ".DS_Store",
+    ".boxdrop-partial-*",
+];
+
+/// Extended attribute the official Dropbox client uses to mark ignored paths
+pub const IGNORED_XATTR: &str = "user.com.dropbox.ignored";
+
+/// Where an ignore rule was defined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleSource {
+    /// The `user.com.dropbox.ignored` attribute on this path or a parent
+    Xattr(PathBuf),
+    Builtin,
+    /// `ignore_patterns` in the configuration
+    Config,
+    /// A `.boxdropignore` file
+    File(PathBuf),
+}
+
+impl fmt::Display for RuleSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleSource::Xattr(path) => write!(f, "the {} attribute of {}", IGNORED_XATTR, path.display()),
+            RuleSource::Builtin => write!(f, "built-in defaults"),
+            RuleSource::Config => write!(f, "ignore_patterns in the configuration"),
+            RuleSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// The rule that decided whether a path is ignored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleMatch {
+    pub source: RuleSource,
+    pub pattern: String,
+    /// The rule is a `!pattern` that re-includes the path
+    pub whitelist: bool,
+}
+
+/// Gitignore-style rules deciding which local paths are never synced
+///
+/// A path marked with the ignored attribute is always ignored. Otherwise rules
+/// from deeper `.boxdropignore` files take precedence over shallower ones,
+/// which take precedence over configured and then built-in patterns.
+#[derive(Clone)]
+pub struct IgnoreRules {
+    root: PathBuf,
+    builtin: Gitignore,
+    config: Gitignore,
+    /// Parsed ignore files keyed by the folder containing them
+    files: BTreeMap<PathBuf, Gitignore>,
+}
+
+impl IgnoreRules {
+    /// Build the rules for `root`, reading every `.boxdropignore` below it
+    ///
+    /// `stores` are the daemon's own folders, such as the trash; those inside
+    /// `root` are ignored as built-in rules.
+    pub fn load(root: &Path, global: &[String], stores: &[PathBuf]) -> Result<Self> {
+        let store_patterns: Vec<String> = stores.iter().filter_map(|store| store_pattern(root, store)).collect();
+        let builtin = BUILTIN_PATTERNS.iter().copied().chain(store_patterns.iter().map(String::as_str));
+        let mut rules = Self {
+            root: root.to_path_buf(),
+            builtin: build(root, builtin)?,
+            config: build(root, global.iter().map(String::as_str))?,
+            files: BTreeMap::new(),
+        };
+        rules.reload_files();
+        Ok(rules)
+    }
+
+    /// Re-read the `.boxdropignore` files after one of them changed
+    pub fn reload_files(&mut self) {
+        self.files.clear();
+        let walker = WalkDir::new(&self.root).into_iter();
+        for entry in walker.filter_map(|e| e.ok()) {
+            if entry.file_type().is_file() && entry.file_name() == IGNORE_FILE {
+                let folder = entry.path().parent().unwrap_or(&self.root).to_path_buf();
+                let (gitignore, error) = Gitignore::new(entry.path());
+                if let Some(e) = error {
+                    warn!("Problem in {}: {}", entry.path().display(), e);
+                }
+                self.files.insert(folder, gitignore);
+            }
+        }
+        debug!("Loaded {} ignore files", self.files.len());
+    }
+
+    /// Whether a path is an ignore file, so its change should trigger a reload
+    pub fn is_ignore_file(path: &Path) -> bool {
+        path.file_name().is_some_and(|name| name == IGNORE_FILE)
+    }
+
+    /// Whether a local path must not be synced
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.explain(path, is_dir).is_some_and(|rule| !rule.whitelist)
+    }
+
+    /// Like [`IgnoreRules::is_ignored`] for an entry of a folder already known not to be ignored
+    ///
+    /// Only the entry's own ignored attribute is read, so a tree walk that
+    /// prunes ignored folders reads one attribute per entry.
+    pub fn is_ignored_entry(&self, path: &Path, is_dir: bool) -> bool {
+        has_ignored_xattr(path) || self.pattern_rule(path, is_dir).is_some_and(|rule| !rule.whitelist)
+    }
+
+    /// The rule deciding whether `path` is ignored, or `None` if no rule matches
+    pub fn explain(&self, path: &Path, is_dir: bool) -> Option<RuleMatch> {
+        if !path.starts_with(&self.root) || path == self.root {
+            return None;
+        }
+
+        if let Some(marked) = self.marked_ancestor(path) {
+            return Some(RuleMatch {
+                pattern: IGNORED_XATTR.to_string(),
+                source: RuleSource::Xattr(marked),
+                whitelist: false,
+            });
+        }
+        self.pattern_rule(path, is_dir)
+    }
+
+    /// The pattern deciding whether `path` is ignored, leaving out the ignored attribute
+    fn pattern_rule(&self, path: &Path, is_dir: bool) -> Option<RuleMatch> {
+        let ancestors: Vec<&Path> = path.ancestors().skip(1).take_while(|dir| dir.starts_with(&self.root)).collect();
+        for dir in ancestors {
+            let Some(gitignore) = self.files.get(dir) else { continue };
+            if let Some(rule) = lookup(gitignore, path, is_dir, || RuleSource::File(dir.join(IGNORE_FILE))) {
+                return Some(rule);
+            }
+        }
+
+        lookup(&self.config, path, is_dir, || RuleSource::Config)
+            .or_else(|| lookup(&self.builtin, path, is_dir, || RuleSource::Builtin))
+    }
+
+    /// Topmost path between `path` and the sync folder carrying the ignored attribute
+    pub fn marked_ancestor(&self, path: &Path) -> Option<PathBuf> {
+        path.ancestors()
+            .take_while(|ancestor| *ancestor != self.root && ancestor.starts_with(&self.root))
+            .filter(|ancestor| has_ignored_xattr(ancestor))
+            .last()
+            .map(Path::to_path_buf)
+    }
+}
+
+/// Whether a path carries the ignored attribute set by the official client or `ignore set`
+pub fn has_ignored_xattr(path: &Path) -> bool {
+    matches!(xattr::get(path, IGNORED_XATTR), Ok(Some(value)) if value == b"1")
+}
+
+/// Set or clear the ignored attribute on a path
+pub fn set_ignored_xattr(path: &Path, ignored: bool) -> Result<()> {
+    let result = if ignored {
+        xattr::set(path, IGNORED_XATTR, b"1")
+    } else if has_ignored_xattr(path) {
+        xattr::remove(path, IGNORED_XATTR)
+    } else {
+        Ok(())
+    };
+    result.map_err(|e| anyhow::anyhow!("Failed to update {} on {}: {}", IGNORED_XATTR, path.display(), e))
+}
+
+/// Anchored pattern for a folder inside `root`, with glob characters escaped
+fn store_pattern(root: &Path, store: &Path) -> Option<String> {
+    let relative = store.strip_prefix(root).ok()?;
+    let parts: Vec<String> = relative.components()
+        .map(|part| part.as_os_str().to_string_lossy()
+            .chars()
+            .flat_map(|c| match c {
+                '*' | '?' | '[' | ']' | '\\' | '!' | '#' => vec!['\\', c],
+                c => vec![c],
+            })
+            .collect())
+        .collect();
+    (!parts.is_empty()).then(|| format!("/{}/", parts.join("/")))
+}
+
+fn build<'a>(root: &Path, patterns: impl Iterator<Item = &'a str>) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in patterns {
+        builder.add_line(None, pattern)
+            .map_err(|e| anyhow::anyhow!("Invalid ignore pattern {}: {}", pattern, e))?;
+    }
+    builder.build()
+        .map_err(|e| anyhow::anyhow!("Failed to build ignore rules: {}", e))
+}
+
+fn lookup(gitignore: &Gitignore, path: &Path, is_dir: bool, source: impl FnOnce() -> RuleSource) -> Option<RuleMatch> {
+    let matched = gitignore.matched_path_or_any_parents(path, is_dir);
+    let glob = matched.inner()?;
+    Some(RuleMatch {
+        source: source(),
+        pattern: glob.original().to_string(),
+        whitelist: matched.is_whitelist(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_builtin_and_config_patterns() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let stores = [root.join("Backups/conflicts"), root.join(".boxdrop-trash"), PathBuf::from("/elsewhere")];
+        let rules = IgnoreRules::load(root, &["node_modules/".to_string()], &stores).unwrap();
+
+        assert!(rules.is_ignored(&root.join("Backups/conflicts/2026-10-18/a.txt"), false));
+        assert!(rules.is_ignored(&root.join(".boxdrop-trash"), true));
+        assert!(!rules.is_ignored(&root.join(".conflicts/mine.txt"), false));
+        assert!(!rules.is_ignored(&root.join("Backups/other.txt"), false));
+        assert!(rules.is_ignored(&root.join("docs/.~lock.report.odt#"), false));
+        assert!(rules.is_ignored(&root.join(".notes.txt.swp"), false));
+        assert!(rules.is_ignored(&root.join("app/node_modules/left-pad/index.js"), false));
+        assert!(!rules.is_ignored(&root.join("app/index.js"), false));
+        assert_eq!(rules.explain(&root.join(".DS_Store"), false).unwrap().source, RuleSource::Builtin);
+    }
+
+    #[test]
+    fn test_nested_files_take_precedence() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("project/keep")).unwrap();
+        std::fs::write(root.join(IGNORE_FILE), "*.log\nbuild/\n").unwrap();
+        std::fs::write(root.join("project/keep").join(IGNORE_FILE), "!important.log\n").unwrap();
+        let rules = IgnoreRules::load(root, &[], &[]).unwrap();
+
+        assert!(rules.is_ignored(&root.join("project/debug.log"), false));
+        assert!(rules.is_ignored(&root.join("project/build/out.o"), false));
+        assert!(!rules.is_ignored(&root.join("project/keep/important.log"), false));
+
+        let rule = rules.explain(&root.join("project/debug.log"), false).unwrap();
+        assert_eq!(rule.pattern, "*.log");
+        assert_eq!(rule.source, RuleSource::File(root.join(IGNORE_FILE)));
+    }
+
+    #[test]
+    fn test_xattr_marks_folder_contents() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("app/build")).unwrap();
+        if set_ignored_xattr(&root.join("app/build"), true).is_err() {
+            return; // file system without user xattrs
+        }
+        std::fs::write(root.join(IGNORE_FILE), "!build/\n").unwrap();
+        let rules = IgnoreRules::load(root, &[], &[]).unwrap();
+
+        assert!(rules.is_ignored(&root.join("app/build/out.o"), false));
+        assert_eq!(rules.marked_ancestor(&root.join("app/build/out.o")), Some(root.join("app/build")));
+
+        set_ignored_xattr(&root.join("app/build"), false).unwrap();
+        assert!(!rules.is_ignored(&root.join("app/build/out.o"), false));
+    }
+}
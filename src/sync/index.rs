@@ -0,0 +1,273 @@
+use crate::{ConfigManager, Result};
+use crate::dropbox::client::{FileMetadata, FolderMetadata};
+use super::paths;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Last synchronized state of a single file or folder
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Dropbox path with its original casing
+    pub path_display: String,
+    /// Dropbox id, stable across moves
+    pub id: String,
+    /// Dropbox revision last seen for this file
+    pub rev: Option<String>,
+    /// Dropbox content hash last seen for this file
+    pub content_hash: Option<String>,
+    pub size: u64,
+    pub client_modified: Option<String>,
+    pub is_dir: bool,
+    /// Local identity of the synced copy, used to recognise local moves
+    pub local: Option<LocalIdentity>,
+}
+
+/// Identity of a local file as reported by `stat(2)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalIdentity {
+    pub device: u64,
+    pub inode: u64,
+    pub size: u64,
+    pub mtime: i64,
+}
+
+impl LocalIdentity {
+    /// Read the identity of a local path without following symlinks
+    pub fn of(path: &Path) -> Option<Self> {
+        std::fs::symlink_metadata(path).ok().map(|m| Self::from_metadata(&m))
+    }
+
+    pub fn from_metadata(metadata: &std::fs::Metadata) -> Self {
+        Self {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+            size: metadata.size(),
+            mtime: metadata.mtime(),
+        }
+    }
+
+    /// Whether both identities refer to the same inode
+    pub fn same_inode(&self, other: &LocalIdentity) -> bool {
+        self.device == other.device && self.inode == other.inode
+    }
+}
+
+impl IndexEntry {
+    pub fn from_file(metadata: &FileMetadata, local: Option<LocalIdentity>) -> Self {
+        Self {
+            path_display: metadata.path_display.clone(),
+            id: metadata.id.clone(),
+            rev: Some(metadata.rev.clone()),
+            content_hash: metadata.content_hash.clone(),
+            size: metadata.size,
+            client_modified: metadata.client_modified.clone(),
+            is_dir: false,
+            local,
+        }
+    }
+
+    pub fn from_folder(metadata: &FolderMetadata, local: Option<LocalIdentity>) -> Self {
+        Self {
+            path_display: metadata.path_display.clone(),
+            id: metadata.id.clone(),
+            rev: None,
+            content_hash: None,
+            size: 0,
+            client_modified: None,
+            is_dir: true,
+            local,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IndexFile {
+    cursor: Option<String>,
+    entries: BTreeMap<String, IndexEntry>,
+}
+
+/// Persistent record of what was last synchronized, keyed by lowercased Dropbox path
+pub struct SyncIndex {
+    path: PathBuf,
+    state: IndexFile,
+}
+
+impl SyncIndex {
+    /// Default location of the sync index inside the data directory
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(ConfigManager::data_dir()?.join("sync_index.json"))
+    }
+
+    /// Load the index from disk, starting empty if it does not exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        let state = if path.exists() {
+            let json = std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read sync index {}: {}", path.display(), e))?;
+            serde_json::from_str(&json)
+                .map_err(|e| anyhow::anyhow!("Failed to parse sync index {}: {}", path.display(), e))?
+        } else {
+            IndexFile::default()
+        };
+
+        debug!("Loaded sync index with {} entries", state.entries.len());
+        Ok(Self { path: path.to_path_buf(), state })
+    }
+
+    /// Persist the index, replacing the previous file atomically
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", parent.display(), e))?;
+        }
+
+        let json = serde_json::to_string(&self.state)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize sync index: {}", e))?;
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)
+            .map_err(|e| anyhow::anyhow!("Failed to write sync index: {}", e))?;
+        std::fs::rename(&tmp, &self.path)
+            .map_err(|e| anyhow::anyhow!("Failed to replace sync index: {}", e))?;
+        Ok(())
+    }
+
+    /// Cursor of the last processed remote delta
+    pub fn cursor(&self) -> Option<&str> {
+        self.state.cursor.as_deref()
+    }
+
+    pub fn set_cursor(&mut self, cursor: String) {
+        self.state.cursor = Some(cursor);
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.entries.is_empty()
+    }
+
+    /// Look up an entry by Dropbox path (any casing)
+    pub fn get(&self, remote: &str) -> Option<&IndexEntry> {
+        self.state.entries.get(&paths::index_key(remote))
+    }
+
+    pub fn insert(&mut self, entry: IndexEntry) {
+        self.state.entries.insert(paths::index_key(&entry.path_display), entry);
+    }
+
+    pub fn remove(&mut self, remote: &str) -> Option<IndexEntry> {
+        self.state.entries.remove(&paths::index_key(remote))
+    }
+
+    /// Iterate over all entries in path order
+    pub fn entries(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.state.entries.values()
+    }
+
+    /// Iterate over the entry at a Dropbox path and everything below it
+    pub fn entries_within<'a>(&'a self, remote: &str) -> impl Iterator<Item = &'a IndexEntry> + 'a {
+        let key = paths::index_key(remote);
+        self.state.entries.iter()
+            .filter(move |(k, _)| paths::is_within(k, &key))
+            .map(|(_, entry)| entry)
+    }
+
+    /// Remove the entry at a Dropbox path and everything below it
+    pub fn remove_within(&mut self, remote: &str) -> Vec<IndexEntry> {
+        let key = paths::index_key(remote);
+        let keys: Vec<String> = self.state.entries.keys()
+            .filter(|k| paths::is_within(k, &key))
+            .cloned()
+            .collect();
+        keys.iter().filter_map(|k| self.state.entries.remove(k)).collect()
+    }
+
+    /// Whether any entry lies strictly below the given Dropbox path
+    pub fn contains_within(&self, remote: &str) -> bool {
+        let key = paths::index_key(remote);
+        self.state.entries.keys().any(|k| k != &key && paths::is_within(k, &key))
+    }
+
+    /// Find the entry carrying a Dropbox id
+    pub fn find_by_id(&self, id: &str) -> Option<&IndexEntry> {
+        self.state.entries.values().find(|entry| entry.id == id)
+    }
+
+    /// Find the entry whose local copy lives on the given inode
+    pub fn find_by_inode(&self, identity: &LocalIdentity) -> Option<&IndexEntry> {
+        self.state.entries.values()
+            .find(|entry| entry.local.is_some_and(|local| local.same_inode(identity)))
+    }
+
+    /// Move an entry and everything below it to a new Dropbox path
+    pub fn rename(&mut self, from: &str, to: &str) {
+        let from_key = paths::index_key(from);
+        let moved: Vec<String> = self.state.entries.keys()
+            .filter(|key| paths::is_within(key, &from_key))
+            .cloned()
+            .collect();
+
+        let depth = from_key.split('/').count();
+        let entries: Vec<IndexEntry> = moved.iter()
+            .filter_map(|key| self.state.entries.remove(key))
+            .collect();
+        for mut entry in entries {
+            let rest: Vec<&str> = entry.path_display.split('/').skip(depth).collect();
+            entry.path_display = std::iter::once(to).chain(rest).collect::<Vec<_>>().join("/");
+            self.state.entries.insert(paths::index_key(&entry.path_display), entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entry(path: &str, id: &str) -> IndexEntry {
+        IndexEntry {
+            path_display: path.to_string(),
+            id: id.to_string(),
+            rev: Some("015".to_string()),
+            content_hash: None,
+            size: 1,
+            client_modified: None,
+            is_dir: false,
+            local: None,
+        }
+    }
+
+    #[test]
+    fn test_rename_moves_descendants() {
+        let dir = TempDir::new().unwrap();
+        let mut index = SyncIndex::load(&dir.path().join("index.json")).unwrap();
+        index.insert(entry("/Photos", "id:1"));
+        index.insert(entry("/Photos/Cat.jpg", "id:2"));
+        index.insert(entry("/PhotosOld/dog.jpg", "id:3"));
+
+        index.rename("/photos", "/Pictures");
+
+        assert_eq!(index.get("/pictures/cat.jpg").unwrap().id, "id:2");
+        assert_eq!(index.find_by_id("id:1").unwrap().path_display, "/Pictures");
+        assert!(index.get("/PhotosOld/dog.jpg").is_some());
+        assert!(index.get("/Photos").is_none());
+    }
+
+    #[test]
+    fn test_save_and_reload() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("index.json");
+        let mut index = SyncIndex::load(&path).unwrap();
+        index.insert(entry("/a.txt", "id:1"));
+        index.set_cursor("cursor-1".to_string());
+        index.save().unwrap();
+
+        let reloaded = SyncIndex::load(&path).unwrap();
+        assert_eq!(reloaded.cursor(), Some("cursor-1"));
+        assert_eq!(reloaded.get("/A.TXT"), index.get("/a.txt"));
+    }
+}
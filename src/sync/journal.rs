@@ -0,0 +1,320 @@
+use crate::{ConfigManager, Result};
+use super::atomic;
+use super::index::SyncIndex;
+use super::names;
+use super::paths;
+use super::planner::SyncAction;
+use super::watcher::LocalChange;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// An engine action as recorded in the journal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalOp {
+    Upload { local: PathBuf, remote: String },
+    Download { remote: String, local: PathBuf },
+    CreateFolder { local: PathBuf, remote: String },
+    MoveRemote { from: String, to: String },
+    MoveLocal { from: PathBuf, to: PathBuf, from_remote: String, to_remote: String },
+    /// `local` and `rev` are missing from journals written before they were recorded
+    DeleteRemote {
+        remote: String,
+        #[serde(default)]
+        local: Option<PathBuf>,
+        /// Revision of the file the deletion was planned against
+        #[serde(default)]
+        rev: Option<String>,
+    },
+    DeleteLocal { local: PathBuf, remote: String },
+}
+
+impl JournalOp {
+    /// The journal record for an action, or `None` for actions that need no recovery
+    pub fn from_action(root: &Path, index: &SyncIndex, action: &SyncAction) -> Option<Self> {
+        let op = match action {
+            SyncAction::Upload { local, remote } => Some(JournalOp::Upload { local: local.clone(), remote: remote.clone() }),
+            SyncAction::Download { metadata, local, .. } => Some(JournalOp::Download {
+                remote: metadata.path_display.clone(),
+                local: local.clone(),
+            }),
+            SyncAction::CreateFolder { local, remote } => Some(JournalOp::CreateFolder { local: local.clone(), remote: remote.clone() }),
+            SyncAction::MoveRemote { from, to } => Some(JournalOp::MoveRemote { from: from.clone(), to: to.clone() }),
+            SyncAction::MoveLocal { from, to, entry } => Some(JournalOp::MoveLocal {
+                from: from.clone(),
+                to: to.clone(),
+                from_remote: paths::remote_path(root, from)?,
+                to_remote: entry.path_display().to_string(),
+            }),
+            SyncAction::DeleteRemote { remote } => Some(JournalOp::DeleteRemote {
+                remote: remote.clone(),
+                local: paths::local_path(root, remote),
+                rev: index.get(remote).and_then(|known| known.rev.clone()),
+            }),
+            SyncAction::DeleteLocal { local, remote } => Some(JournalOp::DeleteLocal { local: local.clone(), remote: remote.clone() }),
+            SyncAction::RenameLocal { .. } | SyncAction::Unsyncable { .. } | SyncAction::TrackFolder { .. } => None,
+        };
+        op.map(|op| op.map_paths(names::lossless_key))
+    }
+
+    /// The same operation with every local path passed through `f`
+    fn map_paths(self, f: impl Fn(&Path) -> PathBuf) -> Self {
+        match self {
+            JournalOp::Upload { local, remote } => JournalOp::Upload { local: f(&local), remote },
+            JournalOp::Download { remote, local } => JournalOp::Download { remote, local: f(&local) },
+            JournalOp::CreateFolder { local, remote } => JournalOp::CreateFolder { local: f(&local), remote },
+            JournalOp::MoveLocal { from, to, from_remote, to_remote } => {
+                JournalOp::MoveLocal { from: f(&from), to: f(&to), from_remote, to_remote }
+            }
+            JournalOp::DeleteRemote { remote, local, rev } => JournalOp::DeleteRemote { remote, local: local.map(|local| f(&local)), rev },
+            JournalOp::DeleteLocal { local, remote } => JournalOp::DeleteLocal { local: f(&local), remote },
+            op @ JournalOp::MoveRemote { .. } => op,
+        }
+    }
+
+    /// The local change an operation was planned for, to plan it again when it never started
+    ///
+    /// Operations taken from Dropbox return `None`; the next remote sync lists them again.
+    pub fn local_change(&self, root: &Path) -> Option<LocalChange> {
+        match self {
+            JournalOp::Upload { local, .. } => Some(LocalChange::Modified(local.clone())),
+            JournalOp::CreateFolder { local, .. } => Some(LocalChange::Created(local.clone())),
+            JournalOp::MoveRemote { from, to } => Some(LocalChange::Moved {
+                from: paths::local_path(root, from)?,
+                to: paths::local_path(root, to)?,
+            }),
+            JournalOp::DeleteRemote { remote, local, .. } => {
+                Some(LocalChange::Removed(local.clone().or_else(|| paths::local_path(root, remote))?))
+            }
+            JournalOp::Download { .. } | JournalOp::MoveLocal { .. } | JournalOp::DeleteLocal { .. } => None,
+        }
+    }
+}
+
+/// How far a journaled action got
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Planned; nothing has been changed yet
+    Intent,
+    /// Started; either side may be partly updated
+    InProgress,
+    /// Finished, though the index may not have been saved since
+    Done,
+}
+
+/// One line of the journal file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Record {
+    seq: u64,
+    stage: Stage,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    op: Option<JournalOp>,
+}
+
+/// An action found in the journal on startup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub stage: Stage,
+    pub op: JournalOp,
+}
+
+/// Write-ahead journal of engine actions, kept next to the sync index
+///
+/// Every action is recorded before it runs and marked as started and done.
+/// Finished entries are cleared whenever the index has been saved, so any
+/// entry left on startup may not be reflected in the index and has to be
+/// checked against the actual local and remote state.
+pub struct Journal {
+    path: PathBuf,
+    file: Option<File>,
+    next_seq: u64,
+    /// Entries not yet cleared, with paths stored losslessly
+    open: BTreeMap<u64, Record>,
+}
+
+impl Journal {
+    /// Location of the journal inside the data directory
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(ConfigManager::data_dir()?.join("journal.jsonl"))
+    }
+
+    /// Open the journal, returning the entries left by the previous run
+    ///
+    /// A torn last line from a crash during a write is ignored.
+    pub fn open(path: &Path) -> Result<(Self, Vec<JournalEntry>)> {
+        let mut ops: BTreeMap<u64, JournalEntry> = BTreeMap::new();
+        if path.exists() {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+            for line in text.lines().filter(|line| !line.trim().is_empty()) {
+                let record: Record = match serde_json::from_str(line) {
+                    Ok(record) => record,
+                    Err(e) => {
+                        warn!("Skipping unreadable journal line in {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+                match record.op {
+                    Some(op) => {
+                        ops.insert(record.seq, JournalEntry { seq: record.seq, stage: record.stage, op });
+                    }
+                    None => {
+                        if let Some(entry) = ops.get_mut(&record.seq) {
+                            entry.stage = entry.stage.max(record.stage);
+                        }
+                    }
+                }
+            }
+        }
+
+        let next_seq = ops.keys().next_back().map_or(0, |seq| seq + 1);
+        let open = ops.values()
+            .map(|entry| (entry.seq, Record { seq: entry.seq, stage: entry.stage, op: Some(entry.op.clone()) }))
+            .collect();
+        let journal = Self { path: path.to_path_buf(), file: None, next_seq, open };
+        let entries = ops.into_values()
+            .map(|entry| JournalEntry { op: entry.op.map_paths(names::local_from_key), ..entry })
+            .collect();
+        Ok((journal, entries))
+    }
+
+    /// Record the intent to perform an action, returning its sequence number
+    ///
+    /// An unfinished entry for the same action, left by an earlier attempt,
+    /// is replaced.
+    pub fn record(&mut self, op: JournalOp) -> Result<u64> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let record = Record { seq, stage: Stage::Intent, op: Some(op) };
+        self.append(&record)?;
+        self.open.retain(|_, open| open.op != record.op);
+        self.open.insert(seq, record);
+        Ok(seq)
+    }
+
+    /// Mark a recorded action as started or done
+    pub fn advance(&mut self, seq: u64, stage: Stage) -> Result<()> {
+        self.append(&Record { seq, stage, op: None })?;
+        if let Some(open) = self.open.get_mut(&seq) {
+            open.stage = open.stage.max(stage);
+        }
+        Ok(())
+    }
+
+    /// Forget the finished entries once the index reflecting them has been saved
+    ///
+    /// Entries that never finished are kept for recovery after a restart.
+    pub fn clear(&mut self) -> Result<()> {
+        self.open.retain(|_, open| open.stage != Stage::Done);
+        self.file = None;
+        if self.open.is_empty() {
+            if self.path.exists() {
+                std::fs::remove_file(&self.path)
+                    .map_err(|e| anyhow::anyhow!("Failed to clear {}: {}", self.path.display(), e))?;
+            }
+            return Ok(());
+        }
+
+        let mut text = String::new();
+        for record in self.open.values() {
+            text.push_str(&serde_json::to_string(record)
+                .map_err(|e| anyhow::anyhow!("Failed to serialize journal record: {}", e))?);
+            text.push('\n');
+        }
+        atomic::write_file(&self.path, text.as_bytes())
+    }
+
+    fn append(&mut self, record: &Record) -> Result<()> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", parent.display(), e))?;
+                }
+                let file = OpenOptions::new().create(true).append(true).open(&self.path)
+                    .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", self.path.display(), e))?;
+                self.file.insert(file)
+            }
+        };
+
+        let mut line = serde_json::to_string(record)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize journal record: {}", e))?;
+        line.push('\n');
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.sync_data())
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn delete_op(remote: &str) -> JournalOp {
+        JournalOp::DeleteRemote { remote: remote.to_string(), local: None, rev: None }
+    }
+
+    #[test]
+    fn test_reopen_reports_latest_stage() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("journal.jsonl");
+
+        let (mut journal, left) = Journal::open(&path).unwrap();
+        assert!(left.is_empty());
+        let upload = journal.record(JournalOp::Upload { local: "/sync/a.txt".into(), remote: "/a.txt".to_string() }).unwrap();
+        let delete = journal.record(delete_op("/b.txt")).unwrap();
+        journal.advance(upload, Stage::InProgress).unwrap();
+        journal.advance(upload, Stage::Done).unwrap();
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"seq\":1,\"sta").unwrap();
+
+        let (mut journal, left) = Journal::open(&path).unwrap();
+        assert_eq!(left.len(), 2);
+        assert_eq!(left[0].stage, Stage::Done);
+        assert_eq!(left[1], JournalEntry { seq: delete, stage: Stage::Intent, op: delete_op("/b.txt") });
+        let recorded = journal.record(delete_op("/c.txt")).unwrap();
+        assert_eq!(recorded, 2);
+
+        for seq in [delete, recorded] {
+            journal.advance(seq, Stage::Done).unwrap();
+        }
+        journal.clear().unwrap();
+        assert!(Journal::open(&path).unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn test_clear_keeps_unfinished_entries() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("journal.jsonl");
+        let (mut journal, _) = Journal::open(&path).unwrap();
+        let done = journal.record(JournalOp::Upload { local: "/sync/a.txt".into(), remote: "/a.txt".to_string() }).unwrap();
+        let failed = journal.record(delete_op("/b.txt")).unwrap();
+        journal.advance(done, Stage::InProgress).unwrap();
+        journal.advance(done, Stage::Done).unwrap();
+        journal.advance(failed, Stage::InProgress).unwrap();
+        journal.clear().unwrap();
+
+        let (mut journal, left) = Journal::open(&path).unwrap();
+        assert_eq!(left, vec![JournalEntry { seq: failed, stage: Stage::InProgress, op: delete_op("/b.txt") }]);
+
+        // Retrying the action supersedes the entry of the failed attempt
+        let retry = journal.record(delete_op("/b.txt")).unwrap();
+        journal.advance(retry, Stage::Done).unwrap();
+        journal.clear().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_older_deletions_still_parse() {
+        let record: Record = serde_json::from_str(r#"{"seq":3,"stage":"intent","op":"delete_remote","remote":"/b.txt"}"#).unwrap();
+        assert_eq!(record.op, Some(delete_op("/b.txt")));
+        assert_eq!(delete_op("/b.txt").local_change(Path::new("/sync")), Some(LocalChange::Removed("/sync/b.txt".into())));
+    }
+}
@@ -1,3 +1,18 @@
+pub mod adopt;
+pub mod atomic;
 pub mod engine;
+pub mod hash_cache;
+pub mod ignore_rules;
+pub mod index;
+pub mod journal;
+pub mod names;
+pub mod offline;
+pub mod paths;
+pub mod permissions;
+pub mod planner;
+pub mod safety;
+pub mod scanner;
+pub mod selective;
+pub mod watcher;
 
-pub use engine::SyncEngine; 
\ No newline at end of file
+pub use engine::SyncEngine;
\ No newline at end of file
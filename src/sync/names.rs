@@ -0,0 +1,240 @@
+use crate::{ConfigManager, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Component, Path, PathBuf};
+
+/// Longest name component Dropbox accepts, in UTF-8 bytes
+pub const MAX_NAME_BYTES: usize = 255;
+
+/// Characters Dropbox refuses in names
+const FORBIDDEN: &[char] = &['\\', '<', '>', ':', '"', '|', '?', '*'];
+
+/// Start of the private-use block escaped bytes are mapped into
+///
+/// Byte `b` is stored as `U+F000 + b`, the same scheme SMB servers use for
+/// characters Windows rejects, so escaped names decode back exactly.
+const ESCAPE_BASE: u32 = 0xF000;
+
+/// Why a local name cannot be stored on Dropbox as is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unsyncable {
+    InvalidUtf8,
+    ForbiddenCharacter(char),
+    ControlCharacter,
+    TrailingSpaceOrDot,
+    TooLong(usize),
+}
+
+impl fmt::Display for Unsyncable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unsyncable::InvalidUtf8 => write!(f, "name is not valid UTF-8"),
+            Unsyncable::ForbiddenCharacter(c) => write!(f, "name contains '{}', which Dropbox does not allow", c),
+            Unsyncable::ControlCharacter => write!(f, "name contains control characters"),
+            Unsyncable::TrailingSpaceOrDot => write!(f, "name ends with a space or a dot"),
+            Unsyncable::TooLong(bytes) => write!(f, "name is {} bytes long; Dropbox allows {}", bytes, MAX_NAME_BYTES),
+        }
+    }
+}
+
+/// Check a single name component against Dropbox's naming rules
+pub fn check_name(name: &OsStr) -> Option<Unsyncable> {
+    let Some(name) = name.to_str() else {
+        return Some(Unsyncable::InvalidUtf8);
+    };
+    if let Some(c) = name.chars().find(|c| FORBIDDEN.contains(c)) {
+        return Some(Unsyncable::ForbiddenCharacter(c));
+    }
+    if name.chars().any(char::is_control) {
+        return Some(Unsyncable::ControlCharacter);
+    }
+    if name.ends_with(' ') || name.ends_with('.') {
+        return Some(Unsyncable::TrailingSpaceOrDot);
+    }
+    if name.len() > MAX_NAME_BYTES {
+        return Some(Unsyncable::TooLong(name.len()));
+    }
+    None
+}
+
+/// Check every component of `path` below `root`
+///
+/// With `escaping` enabled, only problems the escape mapping cannot solve are reported.
+pub fn check_path(root: &Path, path: &Path, escaping: bool) -> Option<Unsyncable> {
+    let relative = path.strip_prefix(root).ok()?;
+    relative.components().find_map(|component| {
+        let Component::Normal(name) = component else { return None };
+        if escaping {
+            let escaped = encode_name(name);
+            (escaped.len() > MAX_NAME_BYTES).then_some(Unsyncable::TooLong(escaped.len()))
+        } else {
+            check_name(name)
+        }
+    })
+}
+
+/// Name used on Dropbox for a local name, escaping what Dropbox refuses
+///
+/// Valid names are returned unchanged.
+pub fn encode_name(name: &OsStr) -> String {
+    if check_name(name).is_none() {
+        return name.to_string_lossy().into_owned();
+    }
+
+    let mut encoded = String::new();
+    for chunk in name.as_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if FORBIDDEN.contains(&c) || c.is_control() {
+                encoded.push(escape_byte(c as u8));
+            } else {
+                encoded.push(c);
+            }
+        }
+        for &byte in chunk.invalid() {
+            encoded.push(escape_byte(byte));
+        }
+    }
+
+    let trimmed = encoded.trim_end_matches([' ', '.']).len();
+    let trailing: String = encoded[trimmed..].bytes().map(escape_byte).collect();
+    encoded.truncate(trimmed);
+    encoded.push_str(&trailing);
+    encoded
+}
+
+/// Local name for a Dropbox name, undoing [`encode_name`]
+///
+/// Only bytes `encode_name` escapes are decoded, and spaces and dots only at
+/// the end of the name, so a name from Dropbox can never decode to `/`, NUL,
+/// `.` or `..`. Other private-use characters are kept as they are.
+pub fn decode_name(name: &str) -> OsString {
+    let chars: Vec<char> = name.chars().collect();
+    let tail = chars.len() - chars.iter().rev()
+        .take_while(|&&c| matches!(unescape(c), Some(b' ' | b'.')))
+        .count();
+
+    let mut bytes = Vec::with_capacity(name.len());
+    for (i, &c) in chars.iter().enumerate() {
+        match unescape(c) {
+            Some(b' ' | b'.') if i < tail => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            Some(byte) => bytes.push(byte),
+            None => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    if bytes == b"." || bytes == b".." {
+        return OsString::from(name);
+    }
+    OsString::from_vec(bytes)
+}
+
+fn escape_byte(byte: u8) -> char {
+    char::from_u32(ESCAPE_BASE + u32::from(byte)).expect("private-use code point")
+}
+
+/// The byte an escaped character stands for, if `encode_name` could have produced it
+fn unescape(c: char) -> Option<u8> {
+    let byte = u8::try_from(u32::from(c).checked_sub(ESCAPE_BASE)?).ok()?;
+    let escaped = FORBIDDEN.contains(&char::from(byte))
+        || (byte.is_ascii_control() && byte != 0)
+        || byte >= 0x80
+        || byte == b' '
+        || byte == b'.';
+    escaped.then_some(byte)
+}
+
+/// Local paths Dropbox would refuse, shared between the daemon and the CLI
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UnsyncableLog {
+    /// Reason keyed by local path
+    pub entries: BTreeMap<PathBuf, String>,
+}
+
+impl UnsyncableLog {
+    /// Location of the unsyncable log inside the data directory
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(ConfigManager::data_dir()?.join("unsyncable.json"))
+    }
+
+    /// Load the log, returning an empty one if none was saved
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize unsyncable paths: {}", e))?;
+        std::fs::write(path, json)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Record a path, returning whether it was not recorded before
+    pub fn record(&mut self, path: &Path, reason: &Unsyncable) -> bool {
+        self.entries.insert(lossless_key(path), reason.to_string()).is_none()
+    }
+
+    /// Forget paths that no longer exist, returning whether any were removed
+    pub fn prune(&mut self) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|path, _| std::fs::symlink_metadata(local_from_key(path)).is_ok());
+        self.entries.len() != before
+    }
+}
+
+/// Key under which a path can be stored in JSON, escaping invalid UTF-8
+pub fn lossless_key(path: &Path) -> PathBuf {
+    path.components()
+        .map(|component| match component {
+            Component::Normal(name) if name.to_str().is_none() => OsString::from(encode_name(name)),
+            other => other.as_os_str().to_os_string(),
+        })
+        .collect()
+}
+
+/// Local path for a key made by [`lossless_key`]
+pub fn local_from_key(key: &Path) -> PathBuf {
+    key.components()
+        .map(|component| match component {
+            Component::Normal(name) => decode_name(&name.to_string_lossy()),
+            other => other.as_os_str().to_os_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_refused_names() {
+        assert_eq!(check_name(OsStr::new("report.pdf")), None);
+        assert_eq!(check_name(OsStr::new("what?.txt")), Some(Unsyncable::ForbiddenCharacter('?')));
+        assert_eq!(check_name(OsStr::new("notes. ")), Some(Unsyncable::TrailingSpaceOrDot));
+        assert_eq!(check_name(OsStr::from_bytes(b"caf\xe9.txt")), Some(Unsyncable::InvalidUtf8));
+        assert_eq!(check_name(OsStr::new(&"a".repeat(300))), Some(Unsyncable::TooLong(300)));
+    }
+
+    #[test]
+    fn test_escape_round_trip() {
+        for name in [&b"what?.txt"[..], b"a:b|c", b"trailing. ", b"caf\xe9.txt", b"tab\there"] {
+            let name = OsStr::from_bytes(name);
+            let encoded = encode_name(name);
+            assert_eq!(check_name(OsStr::new(&encoded)), None, "{:?}", encoded);
+            assert_eq!(decode_name(&encoded), name);
+        }
+        assert_eq!(encode_name(OsStr::new("plain.txt")), "plain.txt");
+        assert_eq!(decode_name("plain.txt"), OsStr::new("plain.txt"));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{ConfigManager, Result};
+use super::atomic;
+use super::names;
+use super::watcher::LocalChange;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Wait before the first connectivity probe after the connection is lost
+pub const PROBE_INITIAL: Duration = Duration::from_secs(5);
+
+/// Longest wait between connectivity probes while offline
+pub const PROBE_MAX: Duration = Duration::from_secs(300);
+
+/// Local changes waiting for Dropbox to become reachable, shared between the daemon and the CLI
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OfflineQueue {
+    /// When the connection was lost, or `None` while online
+    pub offline_since: Option<DateTime<Utc>>,
+    /// Local changes seen while offline, oldest first, with paths stored losslessly
+    pending: Vec<LocalChange>,
+}
+
+impl OfflineQueue {
+    /// Location of the offline queue inside the data directory
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(ConfigManager::data_dir()?.join("offline_queue.json"))
+    }
+
+    /// Load the queue, returning an empty one if none was saved
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize offline queue: {}", e))?;
+        atomic::write_file(path, json.as_bytes())
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline_since.is_some()
+    }
+
+    /// Enter offline mode, keeping the time the connection was first lost
+    pub fn go_offline(&mut self) {
+        self.offline_since.get_or_insert_with(Utc::now);
+    }
+
+    /// Queue local changes for when the connection returns, skipping repeats
+    pub fn push(&mut self, changes: impl IntoIterator<Item = LocalChange>) {
+        for change in changes {
+            let change = change.map_paths(names::lossless_key);
+            if !self.pending.contains(&change) {
+                self.pending.push(change);
+            }
+        }
+    }
+
+    /// Queued changes in the order they were seen
+    pub fn pending(&self) -> Vec<LocalChange> {
+        self.pending.iter().cloned().map(|change| change.map_paths(names::local_from_key)).collect()
+    }
+
+    /// Number of distinct paths with queued changes
+    pub fn pending_paths(&self) -> usize {
+        self.pending.iter().map(LocalChange::path).collect::<HashSet<_>>().len()
+    }
+
+    /// Leave offline mode once the queued changes have been replayed
+    ///
+    /// Changes that failed to replay stay queued, so they are still retried
+    /// after a restart.
+    pub fn reconnected(&mut self, failed: Vec<LocalChange>) {
+        *self = Self::default();
+        self.push(failed);
+    }
+}
+
+/// Exponential backoff between connectivity probes
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    next: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self { next: PROBE_INITIAL }
+    }
+}
+
+impl Backoff {
+    /// Delay before the next probe, doubling each time up to [`PROBE_MAX`]
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(PROBE_MAX);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.next = PROBE_INITIAL;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_queue_survives_restart() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("offline_queue.json");
+        let odd = dir.path().join(OsStr::from_bytes(b"caf\xe9.txt"));
+
+        let mut queue = OfflineQueue::default();
+        queue.go_offline();
+        let since = queue.offline_since;
+        queue.push([LocalChange::Modified(odd.clone()), LocalChange::Created(dir.path().join("a.txt"))]);
+        queue.push([LocalChange::Modified(odd.clone())]);
+        queue.go_offline();
+        queue.save(&path).unwrap();
+
+        let loaded = OfflineQueue::load(&path).unwrap();
+        assert_eq!(loaded.offline_since, since);
+        assert_eq!(loaded.pending_paths(), 2);
+        assert_eq!(loaded.pending()[0], LocalChange::Modified(odd.clone()));
+
+        let mut queue = loaded;
+        queue.reconnected(vec![LocalChange::Modified(odd)]);
+        assert!(!queue.is_offline());
+        assert_eq!(queue.pending_paths(), 1);
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_limit() {
+        let mut backoff = Backoff::default();
+        assert_eq!(backoff.next_delay(), PROBE_INITIAL);
+        assert_eq!(backoff.next_delay(), PROBE_INITIAL * 2);
+        for _ in 0..10 {
+            backoff.next_delay();
+        }
+        assert_eq!(backoff.next_delay(), PROBE_MAX);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), PROBE_INITIAL);
+    }
+}
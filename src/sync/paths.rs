@@ -0,0 +1,200 @@
+use super::names;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+
+/// Label given to a local file renamed because its name clashed with another
+pub const NAME_CONFLICT_LABEL: &str = "case conflict";
+
+/// Map a local path inside the sync folder to its Dropbox path
+///
+/// The sync folder root maps to the Dropbox root, so `<root>/a/b.txt`
+/// becomes `/a/b.txt`. Names are normalized to NFC and names Dropbox refuses
+/// are escaped. Returns `None` for paths outside the sync folder.
+pub fn remote_path(root: &Path, local: &Path) -> Option<String> {
+    let relative = local.strip_prefix(root).ok()?;
+    let mut remote = String::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(name) => {
+                remote.push('/');
+                remote.push_str(&normalize_name(&names::encode_name(name)));
+            }
+            _ => return None,
+        }
+    }
+    Some(remote)
+}
+
+/// Map a Dropbox path to its location inside the sync folder
+///
+/// Names are normalized to NFC, so names created on macOS in NFD land under
+/// the same local name a Linux user would type, and escaped names are decoded.
+/// A `.` or `..` name is kept in its escaped form. Returns `None` if the
+/// result would still leave `root`.
+pub fn local_path(root: &Path, remote: &str) -> Option<PathBuf> {
+    let path = remote
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .fold(root.to_path_buf(), |path, part| {
+            let name = names::decode_name(&normalize_name(part));
+            match name.as_bytes() {
+                b"." | b".." => path.join(names::encode_name(&name)),
+                _ => path.join(name),
+            }
+        });
+    let relative = path.strip_prefix(root).ok()?;
+    relative.components().all(|c| matches!(c, Component::Normal(_))).then_some(path)
+}
+
+/// Key used to look up a Dropbox path in the sync index
+///
+/// Dropbox compares paths case-insensitively, matching its `path_lower`;
+/// the key is also NFC so both Unicode forms of a name share one entry.
+pub fn index_key(remote: &str) -> String {
+    remote.to_lowercase().nfc().collect()
+}
+
+/// A file or folder name in Unicode normalization form C
+pub fn normalize_name(name: &str) -> String {
+    name.nfc().collect()
+}
+
+/// Whether a local name is already in the form used for synced names
+pub fn is_normalized(name: &str) -> bool {
+    unicode_normalization::is_nfc(name)
+}
+
+/// Another entry in the same local folder whose name Dropbox treats as equal
+///
+/// `Report.pdf` and `report.pdf` can live side by side on Linux but would
+/// overwrite each other on Dropbox.
+pub fn case_collision(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?;
+    let key = index_key(&name.to_string_lossy());
+    let siblings = std::fs::read_dir(path.parent()?).ok()?;
+    siblings
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name())
+        .filter(|other| other != name && index_key(&other.to_string_lossy()) == key)
+        .min()
+        .map(|other| path.with_file_name(other))
+}
+
+/// Deterministic name for a local file that has to give way to another
+///
+/// Tries `name (case conflict).ext`, then `name (case conflict 2).ext` and so
+/// on until the name is free, also case-insensitively.
+pub fn collision_free(path: &Path) -> PathBuf {
+    (1..)
+        .map(|n| match n {
+            1 => labelled_sibling(path, NAME_CONFLICT_LABEL),
+            n => labelled_sibling(path, &format!("{} {}", NAME_CONFLICT_LABEL, n)),
+        })
+        .find(|candidate| std::fs::symlink_metadata(candidate).is_err() && case_collision(candidate).is_none())
+        .expect("an unused name exists")
+}
+
+/// Whether `path` equals `prefix` or lies below it, comparing index keys
+pub fn is_within(path: &str, prefix: &str) -> bool {
+    path == prefix
+        || (path.starts_with(prefix) && path.as_bytes().get(prefix.len()) == Some(&b'/'))
+}
+
+/// Replace the `from` prefix of `path` with `to`
+pub fn rebase(path: &str, from: &str, to: &str) -> Option<String> {
+    if is_within(path, from) {
+        Some(format!("{}{}", to, &path[from.len()..]))
+    } else {
+        None
+    }
+}
+
+/// Sibling of `path` with ` (label)` inserted before the extension
+///
+/// `report.pdf` becomes `report (label).pdf`; names without an extension or
+/// starting with a dot get the label appended.
+pub fn labelled_sibling(path: &Path, label: &str) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let renamed = match name.rfind('.') {
+        Some(dot) if dot > 0 => format!("{} ({}){}", &name[..dot], label, &name[dot..]),
+        _ => format!("{} ({})", name, label),
+    };
+    path.with_file_name(renamed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let root = Path::new("/home/user/Dropbox");
+        let local = root.join("Photos").join("cat.jpg");
+        let remote = remote_path(root, &local).unwrap();
+        assert_eq!(remote, "/Photos/cat.jpg");
+        assert_eq!(local_path(root, &remote), Some(local));
+        assert_eq!(remote_path(root, Path::new("/etc/passwd")), None);
+    }
+
+    #[test]
+    fn test_escaped_names_round_trip() {
+        use std::os::unix::ffi::OsStrExt;
+        let root = Path::new("/sync");
+        let local = root.join(std::ffi::OsStr::from_bytes(b"caf\xe9?")).join("notes.");
+        let remote = remote_path(root, &local).unwrap();
+        assert_eq!(local_path(root, &remote), Some(local));
+    }
+
+    #[test]
+    fn test_escaped_names_stay_inside_the_root() {
+        let root = Path::new("/home/u/Dropbox");
+        let path = local_path(root, "/Shared/\u{F02F}etc\u{F02F}cron.d\u{F02F}evil");
+        assert_eq!(path, Some(root.join("Shared").join("\u{F02F}etc\u{F02F}cron.d\u{F02F}evil")));
+
+        let path = local_path(root, "/Shared/\u{F02E}\u{F02E}/x");
+        assert_eq!(path, Some(root.join("Shared").join("\u{F02E}\u{F02E}").join("x")));
+        let path = local_path(root, "/Shared/../x");
+        assert_eq!(path, Some(root.join("Shared").join("\u{F02E}\u{F02E}").join("x")));
+        assert_eq!(local_path(root, "/a/\u{F000}b"), Some(root.join("a").join("\u{F000}b")));
+        // Only the part below the root is checked
+        assert!(local_path(Path::new("/home/u/../u/Dropbox"), "/a").is_some());
+    }
+
+    #[test]
+    fn test_unicode_forms_share_a_key() {
+        let nfd = "/Cafe\u{301}/Re\u{301}sume\u{301}.txt";
+        let nfc = "/Caf\u{e9}/R\u{e9}sum\u{e9}.txt";
+        assert_eq!(index_key(nfd), index_key(nfc));
+        assert_eq!(local_path(Path::new("/sync"), nfd), Some(Path::new("/sync").join("Caf\u{e9}").join("R\u{e9}sum\u{e9}.txt")));
+        assert!(!is_normalized("Cafe\u{301}"));
+    }
+
+    #[test]
+    fn test_case_collision_gets_deterministic_name() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("Report.pdf"), b"a").unwrap();
+        std::fs::write(dir.path().join("report.pdf"), b"b").unwrap();
+        std::fs::write(dir.path().join("report (case conflict).pdf"), b"c").unwrap();
+
+        let ours = dir.path().join("report.pdf");
+        assert_eq!(case_collision(&ours), Some(dir.path().join("Report.pdf")));
+        assert_eq!(collision_free(&ours), dir.path().join("report (case conflict 2).pdf"));
+        assert_eq!(case_collision(&dir.path().join("notes.txt")), None);
+    }
+
+    #[test]
+    fn test_labelled_sibling() {
+        let label = "conflicted copy 2026-10-18";
+        assert_eq!(labelled_sibling(Path::new("/d/report.pdf"), label), Path::new("/d/report (conflicted copy 2026-10-18).pdf"));
+        assert_eq!(labelled_sibling(Path::new("/d/Makefile"), label), Path::new("/d/Makefile (conflicted copy 2026-10-18)"));
+        assert_eq!(labelled_sibling(Path::new("/d/.bashrc"), label), Path::new("/d/.bashrc (conflicted copy 2026-10-18)"));
+    }
+
+    #[test]
+    fn test_rebase_respects_component_boundaries() {
+        assert_eq!(rebase("/a/b/c.txt", "/a/b", "/x"), Some("/x/c.txt".to_string()));
+        assert_eq!(rebase("/a/b", "/a/b", "/x"), Some("/x".to_string()));
+        assert_eq!(rebase("/a/bc.txt", "/a/b", "/x"), None);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::Result;
+use crate::dropbox::properties::PropertyField;
+use std::path::Path;
+use tracing::debug;
+
+/// Name of the Dropbox property template holding POSIX attributes
+pub const TEMPLATE_NAME: &str = "BoxDrop POSIX attributes";
+
+pub const TEMPLATE_DESCRIPTION: &str = "Permission bits and owner names of files synced from Linux";
+
+/// Fields of the property template
+pub const TEMPLATE_FIELDS: &[&str] = &["mode", "owner", "group"];
+
+/// Mode bits that travel with a file
+///
+/// Setuid, setgid and sticky are never synced: anyone able to write to the
+/// Dropbox could otherwise set them on local files.
+const PERMISSION_BITS: u32 = 0o777;
+
+/// POSIX attributes carried alongside a file on Dropbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PosixAttributes {
+    /// Read, write and execute bits for owner, group and others
+    pub mode: u32,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+}
+
+impl PosixAttributes {
+    /// Read the attributes of a local file, or `None` where the platform has no mode bits
+    #[cfg(unix)]
+    pub fn read(path: &Path, with_owners: bool) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            mode: metadata.mode() & PERMISSION_BITS,
+            owner: with_owners.then(|| owners::user_name(metadata.uid())).flatten(),
+            group: with_owners.then(|| owners::group_name(metadata.gid())).flatten(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn read(_path: &Path, _with_owners: bool) -> Option<Self> {
+        None
+    }
+
+    /// Encode as property fields, leaving out unknown owner names
+    pub fn to_fields(&self) -> Vec<PropertyField> {
+        let mut fields = vec![PropertyField::new("mode", format!("{:o}", self.mode))];
+        fields.extend(self.owner.as_ref().map(|owner| PropertyField::new("owner", owner.as_str())));
+        fields.extend(self.group.as_ref().map(|group| PropertyField::new("group", group.as_str())));
+        fields
+    }
+
+    /// Decode property fields, returning `None` without a valid mode
+    pub fn from_fields(fields: &[PropertyField]) -> Option<Self> {
+        let value = |name: &str| fields.iter()
+            .find(|field| field.name == name && !field.value.is_empty())
+            .map(|field| field.value.clone());
+        let mode = u32::from_str_radix(&value("mode")?, 8).ok()?;
+        Some(Self { mode: mode & PERMISSION_BITS, owner: value("owner"), group: value("group") })
+    }
+
+    /// Apply the attributes to a local file
+    ///
+    /// Owner names that do not exist locally, or cannot be applied without
+    /// privileges, are skipped.
+    #[cfg(unix)]
+    pub fn apply(&self, path: &Path, with_owners: bool) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(self.mode & PERMISSION_BITS))
+            .map_err(|e| anyhow::anyhow!("Failed to set mode of {}: {}", path.display(), e))?;
+
+        if with_owners {
+            let uid = self.owner.as_deref().and_then(owners::user_id);
+            let gid = self.group.as_deref().and_then(owners::group_id);
+            if uid.is_some() || gid.is_some() {
+                if let Err(e) = std::os::unix::fs::chown(path, uid, gid) {
+                    debug!("Keeping owner of {}: {}", path.display(), e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply(&self, path: &Path, _with_owners: bool) -> Result<()> {
+        debug!("Cannot apply POSIX attributes to {} on this platform", path.display());
+        Ok(())
+    }
+}
+
+/// Lookups between numeric ids and user or group names
+#[cfg(target_os = "linux")]
+mod owners {
+    use std::ffi::{CStr, CString};
+
+    const BUFFER_SIZE: usize = 16 * 1024;
+
+    pub fn user_name(uid: u32) -> Option<String> {
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut buffer = vec![0 as libc::c_char; BUFFER_SIZE];
+        let mut result = std::ptr::null_mut();
+        // SAFETY: all pointers refer to live buffers of the advertised size
+        let rc = unsafe { libc::getpwuid_r(uid, &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result) };
+        if rc != 0 || result.is_null() {
+            return None;
+        }
+        // SAFETY: on success pw_name points to a NUL-terminated string inside `buffer`
+        unsafe { CStr::from_ptr(entry.pw_name) }.to_str().ok().map(str::to_string)
+    }
+
+    pub fn group_name(gid: u32) -> Option<String> {
+        let mut entry: libc::group = unsafe { std::mem::zeroed() };
+        let mut buffer = vec![0 as libc::c_char; BUFFER_SIZE];
+        let mut result = std::ptr::null_mut();
+        // SAFETY: all pointers refer to live buffers of the advertised size
+        let rc = unsafe { libc::getgrgid_r(gid, &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result) };
+        if rc != 0 || result.is_null() {
+            return None;
+        }
+        // SAFETY: on success gr_name points to a NUL-terminated string inside `buffer`
+        unsafe { CStr::from_ptr(entry.gr_name) }.to_str().ok().map(str::to_string)
+    }
+
+    pub fn user_id(name: &str) -> Option<u32> {
+        let name = CString::new(name).ok()?;
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut buffer = vec![0 as libc::c_char; BUFFER_SIZE];
+        let mut result = std::ptr::null_mut();
+        // SAFETY: all pointers refer to live buffers of the advertised size
+        let rc = unsafe { libc::getpwnam_r(name.as_ptr(), &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result) };
+        (rc == 0 && !result.is_null()).then_some(entry.pw_uid)
+    }
+
+    pub fn group_id(name: &str) -> Option<u32> {
+        let name = CString::new(name).ok()?;
+        let mut entry: libc::group = unsafe { std::mem::zeroed() };
+        let mut buffer = vec![0 as libc::c_char; BUFFER_SIZE];
+        let mut result = std::ptr::null_mut();
+        // SAFETY: all pointers refer to live buffers of the advertised size
+        let rc = unsafe { libc::getgrnam_r(name.as_ptr(), &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result) };
+        (rc == 0 && !result.is_null()).then_some(entry.gr_gid)
+    }
+}
+
+/// Owner names are only resolved on Linux; elsewhere only the mode travels
+#[cfg(all(unix, not(target_os = "linux")))]
+mod owners {
+    pub fn user_name(_uid: u32) -> Option<String> { None }
+    pub fn group_name(_gid: u32) -> Option<String> { None }
+    pub fn user_id(_name: &str) -> Option<u32> { None }
+    pub fn group_id(_name: &str) -> Option<u32> { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_mode_round_trip_through_properties() {
+        let dir = TempDir::new().unwrap();
+        let script = dir.path().join("build.sh");
+        std::fs::write(&script, b"#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o750)).unwrap();
+
+        let attributes = PosixAttributes::read(&script, false).unwrap();
+        assert_eq!(attributes.to_fields(), vec![PropertyField::new("mode", "750")]);
+
+        let copy = dir.path().join("copy.sh");
+        std::fs::write(&copy, b"#!/bin/sh\n").unwrap();
+        PosixAttributes::from_fields(&attributes.to_fields()).unwrap().apply(&copy, false).unwrap();
+        assert_eq!(std::fs::metadata(&copy).unwrap().permissions().mode() & 0o7777, 0o750);
+        assert_eq!(PosixAttributes::from_fields(&[PropertyField::new("mode", "rwx")]), None);
+
+        let setuid = PosixAttributes::from_fields(&[PropertyField::new("mode", "4755")]).unwrap();
+        assert_eq!(setuid.mode, 0o755);
+        setuid.apply(&copy, false).unwrap();
+        assert_eq!(std::fs::metadata(&copy).unwrap().permissions().mode() & 0o7777, 0o755);
+        PosixAttributes { mode: 0o6777, owner: None, group: None }.apply(&copy, false).unwrap();
+        assert_eq!(std::fs::metadata(&copy).unwrap().permissions().mode() & 0o7777, 0o777);
+    }
+}
@@ -0,0 +1,675 @@
+use crate::config::manager::SymlinkPolicy;
+use crate::dropbox::client::{FileMetadata, FolderMetadata, RemoteEntry};
+use super::hash_cache::HashCache;
+use super::ignore_rules::IgnoreRules;
+use super::index::{LocalIdentity, SyncIndex};
+use super::names::{self, Unsyncable};
+use super::paths;
+use super::scanner::{self, EntryKind, Scanner};
+use super::watcher::LocalChange;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, warn};
+
+/// A single step the engine should perform to reconcile both sides
+#[derive(Debug, Clone)]
+pub enum SyncAction {
+    /// Upload a local file to Dropbox
+    Upload { local: PathBuf, remote: String },
+    /// Write a Dropbox file to its local path
+    ///
+    /// `expected` is the local identity the write may replace; any other
+    /// state of the target at write time is treated as a conflict.
+    Download { metadata: FileMetadata, local: PathBuf, expected: Option<LocalIdentity> },
+    /// Relocate a Dropbox entry to follow a local move
+    MoveRemote { from: String, to: String },
+    /// Rename a local entry to follow a remote move
+    MoveLocal { from: PathBuf, to: PathBuf, entry: RemoteEntry },
+    /// Give a local file a name that no longer clashes with another on Dropbox
+    RenameLocal { from: PathBuf, to: PathBuf },
+    /// Report a local file whose name Dropbox would refuse
+    Unsyncable { local: PathBuf, reason: Unsyncable },
+    /// Create a local folder for a Dropbox one if missing and record it in the index
+    TrackFolder { metadata: FolderMetadata, local: PathBuf },
+    /// Create a Dropbox folder for a new local one
+    CreateFolder { local: PathBuf, remote: String },
+    /// Delete a Dropbox entry whose local copy was removed
+    DeleteRemote { remote: String },
+    /// Delete a local entry whose Dropbox copy was removed
+    DeleteLocal { local: PathBuf, remote: String },
+}
+
+impl SyncAction {
+    /// Whether the action removes data on either side
+    pub fn is_deletion(&self) -> bool {
+        matches!(self, SyncAction::DeleteRemote { .. } | SyncAction::DeleteLocal { .. })
+    }
+
+    /// Dropbox paths the action reads or writes
+    pub fn remote_paths(&self, root: &Path) -> Vec<String> {
+        let remote = |local: &Path| paths::remote_path(root, local);
+        match self {
+            SyncAction::Upload { remote, .. }
+            | SyncAction::CreateFolder { remote, .. }
+            | SyncAction::DeleteRemote { remote }
+            | SyncAction::DeleteLocal { remote, .. } => vec![remote.clone()],
+            SyncAction::Download { metadata, .. } => vec![metadata.path_display.clone()],
+            SyncAction::TrackFolder { metadata, .. } => vec![metadata.path_display.clone()],
+            SyncAction::MoveRemote { from, to } => vec![from.clone(), to.clone()],
+            SyncAction::MoveLocal { from, entry, .. } => {
+                remote(from).into_iter().chain([entry.path_display().to_string()]).collect()
+            }
+            SyncAction::RenameLocal { from, to } => remote(from).into_iter().chain(remote(to)).collect(),
+            SyncAction::Unsyncable { .. } => Vec::new(),
+        }
+    }
+}
+
+/// Turns observed local and remote changes into sync actions
+pub struct Planner<'a> {
+    root: &'a Path,
+    index: &'a SyncIndex,
+    ignore: Option<&'a Arc<IgnoreRules>>,
+    escape_names: bool,
+    symlinks: SymlinkPolicy,
+    hashes: Option<&'a Mutex<HashCache>>,
+}
+
+impl<'a> Planner<'a> {
+    pub fn new(root: &'a Path, index: &'a SyncIndex) -> Self {
+        Self { root, index, ignore: None, escape_names: false, symlinks: SymlinkPolicy::default(), hashes: None }
+    }
+
+    /// Compare touched files by cached content hash before uploading them again
+    pub fn with_hashes(mut self, hashes: &'a Mutex<HashCache>) -> Self {
+        self.hashes = Some(hashes);
+        self
+    }
+
+    /// Skip paths matched by ignore rules when scanning local folders
+    pub fn with_ignore(mut self, rules: &'a Arc<IgnoreRules>) -> Self {
+        self.ignore = Some(rules);
+        self
+    }
+
+    /// Upload names Dropbox refuses under their escaped form instead of reporting them
+    pub fn escaping_names(mut self, enabled: bool) -> Self {
+        self.escape_names = enabled;
+        self
+    }
+
+    /// Handle symbolic links on either side according to `policy`
+    pub fn with_symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Plan the actions needed to propagate local changes to Dropbox
+    ///
+    /// Renames are recognised first, either from paired watcher events or by
+    /// matching the inode of a newly created path against the index, so that
+    /// they become a Dropbox move instead of a fresh upload.
+    pub fn plan_local(&self, changes: &[LocalChange]) -> Vec<SyncAction> {
+        let mut actions = Vec::new();
+        let mut handled = HashSet::new();
+
+        for change in changes {
+            match change {
+                LocalChange::Moved { from, to } => {
+                    handled.insert(from.clone());
+                    handled.insert(to.clone());
+                    let (Some(from_remote), Some(to_remote)) =
+                        (paths::remote_path(self.root, from), paths::remote_path(self.root, to))
+                    else {
+                        continue;
+                    };
+
+                    if self.index.get(&from_remote).is_some() || self.index.contains_within(&from_remote) {
+                        actions.push(SyncAction::MoveRemote { from: from_remote, to: to_remote });
+                    } else if self.is_unchanged(to, &to_remote) {
+                        debug!("Move of {} already reflected in the index", to.display());
+                    } else {
+                        self.plan_upload(to, &mut actions);
+                    }
+                }
+                LocalChange::Created(to) => {
+                    if let Some((from, from_remote)) = self.find_moved_inode(to) {
+                        if let Some(to_remote) = paths::remote_path(self.root, to) {
+                            debug!("Matched {} to moved inode of {}", to.display(), from.display());
+                            handled.insert(from);
+                            handled.insert(to.clone());
+                            actions.push(SyncAction::MoveRemote { from: from_remote, to: to_remote });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut subtrees = Vec::new();
+        for change in changes {
+            match change {
+                LocalChange::Created(path) | LocalChange::Modified(path) if !handled.contains(path) => {
+                    if path.is_dir() && !path.is_symlink() {
+                        subtrees.push(path.clone());
+                    } else {
+                        self.plan_upload(path, &mut actions);
+                    }
+                }
+                LocalChange::Removed(path) if !handled.contains(path) && !path.exists() => {
+                    let Some(remote) = paths::remote_path(self.root, path) else { continue };
+                    if let Some(known) = self.index.get(&remote) {
+                        actions.push(SyncAction::DeleteRemote { remote: known.path_display.clone() });
+                    } else if self.index.contains_within(&remote) {
+                        actions.push(SyncAction::DeleteRemote { remote });
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.plan_subtrees(&subtrees, &mut actions);
+
+        actions
+    }
+
+    /// Plan the actions needed to apply a batch of Dropbox changes locally
+    ///
+    /// An entry whose id is already indexed at another path was moved on
+    /// Dropbox and becomes a local rename. Children of a moved folder are
+    /// covered by the folder's own rename, and the deletion reported for the
+    /// old path of a moved entry is ignored.
+    pub fn plan_remote(&self, entries: &[RemoteEntry]) -> Vec<SyncAction> {
+        let present_ids: HashSet<&str> = entries.iter()
+            .filter_map(|entry| match entry {
+                RemoteEntry::File(m) => Some(m.id.as_str()),
+                RemoteEntry::Folder(m) => Some(m.id.as_str()),
+                RemoteEntry::Deleted(_) => None,
+            })
+            .collect();
+        let mut actions = Vec::new();
+        let mut folders = Vec::new();
+        let mut moved_prefixes: Vec<(String, String)> = Vec::new();
+
+        for entry in entries {
+            if let RemoteEntry::Folder(metadata) = entry {
+                if let Some(known) = self.index.find_by_id(&metadata.id) {
+                    let old_key = paths::index_key(&known.path_display);
+                    let new_key = paths::index_key(&metadata.path_display);
+                    if old_key != new_key && !Self::covered(&moved_prefixes, &old_key, &new_key) {
+                        let (Some(from), Some(to)) = (
+                            paths::local_path(self.root, &known.path_display),
+                            paths::local_path(self.root, &metadata.path_display),
+                        ) else {
+                            continue;
+                        };
+                        actions.push(SyncAction::MoveLocal { from, to, entry: entry.clone() });
+                        moved_prefixes.push((old_key, new_key));
+                        continue;
+                    }
+                }
+                if let Some(local) = paths::local_path(self.root, &metadata.path_display) {
+                    folders.push(SyncAction::TrackFolder { metadata: metadata.clone(), local });
+                }
+            }
+        }
+
+        for entry in entries {
+            let metadata = match entry {
+                RemoteEntry::File(metadata) => metadata,
+                RemoteEntry::Deleted(deleted) => {
+                    self.plan_remote_deletion(&deleted.path_lower, &present_ids, &mut actions);
+                    continue;
+                }
+                RemoteEntry::Folder(_) => continue,
+            };
+            if metadata.symlink_info.is_some() && self.symlinks != SymlinkPolicy::Store {
+                debug!("Skipping Dropbox symlink {}", metadata.path_display);
+                continue;
+            }
+            let Some(local) = paths::local_path(self.root, &metadata.path_display) else { continue };
+            let new_key = paths::index_key(&metadata.path_display);
+
+            if let Some(known) = self.index.find_by_id(&metadata.id) {
+                let old_key = paths::index_key(&known.path_display);
+                let Some(old_local) = paths::local_path(self.root, &known.path_display) else { continue };
+                if old_key != new_key || old_local != local {
+                    if !Self::covered(&moved_prefixes, &old_key, &new_key) {
+                        actions.push(SyncAction::MoveLocal { from: old_local, to: local.clone(), entry: entry.clone() });
+                    }
+                    if known.rev.as_deref() != Some(metadata.rev.as_str()) && known.content_hash != metadata.content_hash {
+                        actions.push(SyncAction::Download { metadata: metadata.clone(), local, expected: known.local });
+                    }
+                    continue;
+                }
+            }
+
+            match self.index.get(&new_key) {
+                Some(known) if known.rev.as_deref() == Some(metadata.rev.as_str()) => {}
+                Some(known) if known.content_hash.is_some() && known.content_hash == metadata.content_hash => {}
+                Some(known) => actions.push(SyncAction::Download { metadata: metadata.clone(), local, expected: known.local }),
+                None if local.exists() => {
+                    warn!("{} exists locally but is not tracked; leaving it untouched", local.display());
+                }
+                None => {
+                    if let Some(other) = paths::case_collision(&local) {
+                        actions.push(SyncAction::RenameLocal { to: paths::collision_free(&other), from: other });
+                    }
+                    actions.push(SyncAction::Download { metadata: metadata.clone(), local, expected: None });
+                }
+            }
+        }
+
+        actions.extend(folders);
+        actions
+    }
+
+    /// Plan the local side of a Dropbox deletion
+    ///
+    /// A file edited locally since it was last synced is uploaded again rather
+    /// than deleted, so the local edit survives.
+    fn plan_remote_deletion(&self, path_lower: &str, present_ids: &HashSet<&str>, actions: &mut Vec<SyncAction>) {
+        let Some(known) = self.index.get(path_lower) else {
+            if let Some(local) = paths::local_path(self.root, path_lower).filter(|_| self.index.contains_within(path_lower)) {
+                actions.push(SyncAction::DeleteLocal { local, remote: path_lower.to_string() });
+            }
+            return;
+        };
+        if present_ids.contains(known.id.as_str()) {
+            return;
+        }
+
+        let Some(local) = paths::local_path(self.root, &known.path_display) else { return };
+        if !known.is_dir && local.exists() && !self.is_unchanged(&local, &known.path_display) {
+            warn!("{} was deleted on Dropbox but edited locally; keeping the local copy", local.display());
+            actions.push(SyncAction::Upload { local, remote: known.path_display.clone() });
+            return;
+        }
+        actions.push(SyncAction::DeleteLocal { local, remote: known.path_display.clone() });
+    }
+
+    /// Whether a move of `old_key` to `new_key` follows from an already planned folder move
+    fn covered(moved_prefixes: &[(String, String)], old_key: &str, new_key: &str) -> bool {
+        moved_prefixes.iter().any(|(from, to)| {
+            paths::rebase(old_key, from, to).as_deref() == Some(new_key)
+        })
+    }
+
+    /// Find an indexed path whose inode now appears at `path` and which no longer exists
+    fn find_moved_inode(&self, path: &Path) -> Option<(PathBuf, String)> {
+        let identity = LocalIdentity::of(path)?;
+        let known = self.index.find_by_inode(&identity)?;
+        let old_local = paths::local_path(self.root, &known.path_display)?;
+        if old_local == path || old_local.exists() {
+            return None;
+        }
+        Some((old_local, known.path_display.clone()))
+    }
+
+    /// Whether the local file still matches what the index last recorded
+    ///
+    /// A file whose timestamps changed but whose size did not is compared by
+    /// content hash, which the hash cache answers without reading the file
+    /// again on later scans.
+    fn is_unchanged(&self, path: &Path, remote: &str) -> bool {
+        self.matches_index(path, LocalIdentity::of(path), remote)
+    }
+
+    /// [`Planner::is_unchanged`] for a file whose identity was already read
+    fn matches_index(&self, path: &Path, identity: Option<LocalIdentity>, remote: &str) -> bool {
+        let (Some(known), Some(current)) = (self.index.get(remote), identity) else {
+            return false;
+        };
+        if known.local == Some(current) {
+            return true;
+        }
+        match (self.hashes, &known.content_hash) {
+            (Some(hashes), Some(recorded)) if !known.is_dir && known.size == current.size && !path.is_symlink() => {
+                let mut hashes = hashes.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                hashes.content_hash(path).is_ok_and(|hash| &hash == recorded)
+            }
+            _ => false,
+        }
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.ignore.is_some_and(|rules| rules.is_ignored(path, is_dir))
+    }
+
+    fn plan_upload(&self, path: &Path, actions: &mut Vec<SyncAction>) {
+        let Ok(metadata) = std::fs::symlink_metadata(path) else { return };
+        if metadata.is_symlink() && !self.admits_symlink(path) {
+            return;
+        }
+        let stored_link = metadata.is_symlink() && self.symlinks == SymlinkPolicy::Store;
+        if !path.is_dir() || stored_link {
+            self.plan_file(path, actions);
+            return;
+        }
+
+        self.plan_subtrees(&[path.to_path_buf()], actions);
+    }
+
+    /// Plan uploads for everything in the given folders, scanning each folder once
+    fn plan_subtrees(&self, subtrees: &[PathBuf], actions: &mut Vec<SyncAction>) {
+        if subtrees.is_empty() {
+            return;
+        }
+        let mut scanner = Scanner::new(self.root).with_symlinks(self.symlinks);
+        if let Some(rules) = self.ignore {
+            scanner = scanner.with_ignore(Arc::clone(rules));
+        }
+        for entry in scanner.snapshot(subtrees) {
+            match entry.kind {
+                EntryKind::Dir => self.plan_folder(&entry.path, actions),
+                // The scanner already applied the symlink policy and ignore rules
+                EntryKind::File | EntryKind::Symlink => self.plan_admitted_file(&entry.path, entry.identity(), actions),
+            }
+        }
+    }
+
+    fn plan_file(&self, path: &Path, actions: &mut Vec<SyncAction>) {
+        let is_link = path.is_symlink();
+        if is_link && !self.admits_symlink(path) {
+            return;
+        }
+        let stored_link = is_link && self.symlinks == SymlinkPolicy::Store;
+        if (!stored_link && !path.is_file()) || self.is_ignored(path, false) {
+            return;
+        }
+        self.plan_admitted_file(path, LocalIdentity::of(path), actions);
+    }
+
+    /// Plan the upload of a file that passed the symlink policy and ignore rules
+    fn plan_admitted_file(&self, path: &Path, identity: Option<LocalIdentity>, actions: &mut Vec<SyncAction>) {
+        if let Some(reason) = names::check_path(self.root, path, self.escape_names) {
+            actions.push(SyncAction::Unsyncable { local: path.to_path_buf(), reason });
+            return;
+        }
+        let path = self.claim_name(path, actions);
+        let Some(remote) = paths::remote_path(self.root, &path) else { return };
+        if self.matches_index(&path, identity, &remote) {
+            return;
+        }
+        actions.push(SyncAction::Upload { local: path, remote });
+    }
+
+    /// Create a local folder on Dropbox unless it is already known there
+    ///
+    /// Folders are created even when files inside them will be uploaded, so
+    /// empty folders sync and every folder is indexed for move detection.
+    fn plan_folder(&self, path: &Path, actions: &mut Vec<SyncAction>) {
+        let Some(remote) = paths::remote_path(self.root, path) else { return };
+        if remote.is_empty() || self.index.get(&remote).is_some() {
+            return;
+        }
+        if let Some(reason) = names::check_path(self.root, path, self.escape_names) {
+            actions.push(SyncAction::Unsyncable { local: path.to_path_buf(), reason });
+            return;
+        }
+        actions.push(SyncAction::CreateFolder { local: path.to_path_buf(), remote });
+    }
+
+    fn admits_symlink(&self, path: &Path) -> bool {
+        scanner::admits_symlink(self.root, path, self.symlinks)
+    }
+
+    /// Make sure a local file's name maps to a Dropbox path of its own
+    ///
+    /// A name not in NFC is renamed to its NFC form. When another file in the
+    /// folder differs only in case, the name already known to the index, or
+    /// else the one sorting first, is kept and this file is renamed.
+    /// Returns the path the file will have.
+    fn claim_name(&self, path: &Path, actions: &mut Vec<SyncAction>) -> PathBuf {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let target = if paths::is_normalized(&name) {
+            let Some(other) = paths::case_collision(path) else { return path.to_path_buf() };
+            let other_name = other.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let known_name = paths::remote_path(self.root, path)
+                .and_then(|remote| self.index.get(&remote))
+                .and_then(|entry| entry.path_display.rsplit('/').next().map(str::to_string));
+            let keep_ours = match known_name {
+                Some(known) => known == name,
+                None => !paths::is_normalized(&other_name) || name < other_name,
+            };
+            if keep_ours {
+                return path.to_path_buf();
+            }
+            paths::collision_free(path)
+        } else {
+            let normalized = path.with_file_name(paths::normalize_name(&name));
+            if std::fs::symlink_metadata(&normalized).is_ok() || paths::case_collision(&normalized).is_some() {
+                paths::collision_free(&normalized)
+            } else {
+                normalized
+            }
+        };
+
+        actions.push(SyncAction::RenameLocal { from: path.to_path_buf(), to: target.clone() });
+        target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dropbox::client::DeletedMetadata;
+    use crate::sync::index::IndexEntry;
+    use tempfile::TempDir;
+
+    fn file(path: &str, id: &str, rev: &str) -> FileMetadata {
+        FileMetadata {
+            name: path.rsplit('/').next().unwrap().to_string(),
+            path_lower: path.to_lowercase(),
+            path_display: path.to_string(),
+            id: id.to_string(),
+            client_modified: None,
+            server_modified: None,
+            rev: rev.to_string(),
+            size: 1,
+            is_downloadable: true,
+            content_hash: None,
+            symlink_info: None,
+            tag: "file".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_local_rename_detected_by_inode() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("sync");
+        std::fs::create_dir_all(&root).unwrap();
+        let mut index = SyncIndex::load(&dir.path().join("index.json")).unwrap();
+
+        let old = root.join("big.iso");
+        std::fs::write(&old, b"data").unwrap();
+        index.insert(IndexEntry::from_file(&file("/big.iso", "id:1", "a1"), LocalIdentity::of(&old)));
+        let new = root.join("renamed.iso");
+        std::fs::rename(&old, &new).unwrap();
+
+        let planner = Planner::new(&root, &index);
+        let actions = planner.plan_local(&[
+            LocalChange::Removed(old),
+            LocalChange::Created(new),
+        ]);
+
+        assert_eq!(actions.len(), 1);
+        assert!(matches!(&actions[0], SyncAction::MoveRemote { from, to }
+            if from == "/big.iso" && to == "/renamed.iso"));
+    }
+
+    #[test]
+    fn test_touched_files_are_compared_by_cached_hash() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("sync");
+        std::fs::create_dir_all(&root).unwrap();
+        let mut index = SyncIndex::load(&dir.path().join("index.json")).unwrap();
+
+        let notes = root.join("notes.txt");
+        std::fs::write(&notes, b"same").unwrap();
+        let mut metadata = file("/notes.txt", "id:1", "a1");
+        metadata.size = 4;
+        metadata.content_hash = Some(crate::dropbox::content_hash::content_hash(b"same"));
+        index.insert(IndexEntry::from_file(&metadata, LocalIdentity::of(&notes)));
+        let touched = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        std::fs::File::options().write(true).open(&notes).unwrap().set_modified(touched).unwrap();
+
+        let hashes = Mutex::new(HashCache::load(&dir.path().join("hash_cache.json")));
+        let planner = Planner::new(&root, &index).with_hashes(&hashes);
+        assert!(planner.plan_local(&[LocalChange::Modified(notes.clone())]).is_empty());
+        hashes.lock().unwrap().save().unwrap();
+        assert!(dir.path().join("hash_cache.json").exists());
+
+        std::fs::write(&notes, b"edit").unwrap();
+        std::fs::File::options().write(true).open(&notes).unwrap().set_modified(touched).unwrap();
+        let actions = planner.plan_local(&[LocalChange::Modified(notes)]);
+        assert!(matches!(&actions[..], [SyncAction::Upload { remote, .. }] if remote == "/notes.txt"));
+    }
+
+    #[test]
+    fn test_new_local_folders_are_created_on_dropbox() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("sync");
+        std::fs::create_dir_all(root.join("Projects/empty")).unwrap();
+        std::fs::write(root.join("Projects/plan.txt"), b"plan").unwrap();
+        let mut index = SyncIndex::load(&dir.path().join("index.json")).unwrap();
+        index.insert(IndexEntry::from_folder(&FolderMetadata {
+            name: "Projects".to_string(),
+            path_lower: "/projects".to_string(),
+            path_display: "/Projects".to_string(),
+            id: "id:1".to_string(),
+            tag: "folder".to_string(),
+        }, None));
+
+        let planner = Planner::new(&root, &index);
+        let actions = planner.plan_local(&[LocalChange::Created(root.join("Projects"))]);
+
+        assert_eq!(actions.len(), 2);
+        assert!(actions.iter().any(|action| matches!(action, SyncAction::CreateFolder { remote, .. }
+            if remote == "/Projects/empty")));
+        assert!(actions.iter().any(|action| matches!(action, SyncAction::Upload { remote, .. }
+            if remote == "/Projects/plan.txt")));
+    }
+
+    #[test]
+    fn test_remote_move_becomes_local_rename() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("sync");
+        let mut index = SyncIndex::load(&dir.path().join("index.json")).unwrap();
+        index.insert(IndexEntry::from_file(&file("/Docs/a.txt", "id:1", "a1"), None));
+
+        let planner = Planner::new(&root, &index);
+        let actions = planner.plan_remote(&[
+            RemoteEntry::File(file("/Archive/a.txt", "id:1", "a1")),
+        ]);
+
+        assert_eq!(actions.len(), 1);
+        assert!(matches!(&actions[0], SyncAction::MoveLocal { from, to, .. }
+            if *from == root.join("Docs/a.txt") && *to == root.join("Archive/a.txt")));
+    }
+
+    #[test]
+    fn test_deletions_in_both_directions() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("sync");
+        std::fs::create_dir_all(&root).unwrap();
+        let mut index = SyncIndex::load(&dir.path().join("index.json")).unwrap();
+
+        let kept = root.join("kept.txt");
+        std::fs::write(&kept, b"v1").unwrap();
+        index.insert(IndexEntry::from_file(&file("/kept.txt", "id:1", "a1"), LocalIdentity::of(&kept)));
+        index.insert(IndexEntry::from_file(&file("/gone.txt", "id:2", "a1"), None));
+
+        let planner = Planner::new(&root, &index);
+        let local = planner.plan_local(&[LocalChange::Removed(root.join("gone.txt"))]);
+        assert!(matches!(&local[..], [SyncAction::DeleteRemote { remote }] if remote == "/gone.txt"));
+
+        let remote = planner.plan_remote(&[RemoteEntry::Deleted(DeletedMetadata {
+            name: "kept.txt".to_string(),
+            path_lower: "/kept.txt".to_string(),
+            path_display: "/kept.txt".to_string(),
+            tag: "deleted".to_string(),
+        })]);
+        assert!(matches!(&remote[..], [SyncAction::DeleteLocal { local, .. }] if *local == kept));
+    }
+
+    #[test]
+    fn test_remote_folder_move_covers_children() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("sync");
+        let mut index = SyncIndex::load(&dir.path().join("index.json")).unwrap();
+        index.insert(IndexEntry::from_folder(&FolderMetadata {
+            name: "Docs".to_string(),
+            path_lower: "/docs".to_string(),
+            path_display: "/Docs".to_string(),
+            id: "id:folder".to_string(),
+            tag: "folder".to_string(),
+        }, None));
+        index.insert(IndexEntry::from_file(&file("/Docs/a.txt", "id:1", "a1"), None));
+
+        let planner = Planner::new(&root, &index);
+        let actions = planner.plan_remote(&[
+            RemoteEntry::Folder(FolderMetadata {
+                name: "Papers".to_string(),
+                path_lower: "/papers".to_string(),
+                path_display: "/Papers".to_string(),
+                id: "id:folder".to_string(),
+                tag: "folder".to_string(),
+            }),
+            RemoteEntry::File(file("/Papers/a.txt", "id:1", "a1")),
+        ]);
+
+        assert_eq!(actions.len(), 1);
+        assert!(matches!(&actions[0], SyncAction::MoveLocal { to, .. } if *to == root.join("Papers")));
+    }
+
+    #[test]
+    fn test_case_collision_renames_the_newcomer() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("sync");
+        std::fs::create_dir_all(&root).unwrap();
+        let mut index = SyncIndex::load(&dir.path().join("index.json")).unwrap();
+
+        let known = root.join("Report.pdf");
+        std::fs::write(&known, b"v1").unwrap();
+        index.insert(IndexEntry::from_file(&file("/Report.pdf", "id:1", "a1"), LocalIdentity::of(&known)));
+        let newcomer = root.join("report.pdf");
+        std::fs::write(&newcomer, b"other").unwrap();
+
+        let planner = Planner::new(&root, &index);
+        let actions = planner.plan_local(&[LocalChange::Created(newcomer.clone())]);
+
+        let renamed = root.join("report (case conflict).pdf");
+        assert!(matches!(&actions[..], [
+            SyncAction::RenameLocal { from, to },
+            SyncAction::Upload { local, remote },
+        ] if *from == newcomer && *to == renamed && *local == renamed && remote == "/report (case conflict).pdf"));
+    }
+
+    #[test]
+    fn test_symlink_policies() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("sync");
+        std::fs::create_dir_all(root.join("real")).unwrap();
+        std::fs::write(root.join("real/a.txt"), b"a").unwrap();
+        std::os::unix::fs::symlink(&root, root.join("real/loop")).unwrap();
+        std::os::unix::fs::symlink("/", root.join("escape")).unwrap();
+        let index = SyncIndex::load(&dir.path().join("index.json")).unwrap();
+        let uploads = |policy| {
+            let mut remotes: Vec<String> = Planner::new(&root, &index).with_symlinks(policy)
+                .plan_local(&[LocalChange::Created(root.clone())])
+                .into_iter()
+                .filter_map(|action| match action {
+                    SyncAction::Upload { remote, .. } => Some(remote),
+                    _ => None,
+                })
+                .collect();
+            remotes.sort();
+            remotes
+        };
+
+        assert_eq!(uploads(SymlinkPolicy::Skip), vec!["/real/a.txt"]);
+        assert_eq!(uploads(SymlinkPolicy::Follow), vec!["/real/a.txt"]);
+        assert_eq!(uploads(SymlinkPolicy::Store), vec!["/escape", "/real/a.txt", "/real/loop"]);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{ConfigManager, Result};
+use crate::config::manager::DeletionSafety;
+use super::index::SyncIndex;
+use super::paths;
+use super::planner::SyncAction;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The percentage limit only applies once a cycle deletes at least this many files,
+/// so removing a handful of files from a small folder never trips the brake
+const PERCENT_RULE_MIN_FILES: usize = 10;
+
+/// Which side a held deletion would have been applied to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletionSide {
+    /// Delete on Dropbox because the local copy disappeared
+    Remote,
+    /// Delete locally because the Dropbox copy disappeared
+    Local,
+}
+
+/// A deletion held back by the safety brake
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeldDeletion {
+    pub side: DeletionSide,
+    /// Dropbox path of the deleted entry
+    pub remote: String,
+}
+
+impl HeldDeletion {
+    /// Convert a planned deletion, returning `None` for any other action
+    pub fn from_action(action: &SyncAction) -> Option<Self> {
+        match action {
+            SyncAction::DeleteRemote { remote } => Some(Self { side: DeletionSide::Remote, remote: remote.clone() }),
+            SyncAction::DeleteLocal { remote, .. } => Some(Self { side: DeletionSide::Local, remote: remote.clone() }),
+            _ => None,
+        }
+    }
+
+    /// Turn the held deletion back into an action for the engine
+    ///
+    /// Returns `None` for a local deletion whose path maps outside `root`.
+    pub fn to_action(&self, root: &Path) -> Option<SyncAction> {
+        match self.side {
+            DeletionSide::Remote => Some(SyncAction::DeleteRemote { remote: self.remote.clone() }),
+            DeletionSide::Local => Some(SyncAction::DeleteLocal {
+                local: paths::local_path(root, &self.remote)?,
+                remote: self.remote.clone(),
+            }),
+        }
+    }
+}
+
+/// User decision on held deletions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// Propagate the deletions
+    Approve,
+    /// Keep the files and restore the missing side
+    Reject,
+}
+
+/// Deletions waiting for confirmation, shared between the daemon and the CLI
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PendingDeletions {
+    pub held_since: Option<DateTime<Utc>>,
+    pub deletions: Vec<HeldDeletion>,
+    pub decision: Option<Decision>,
+}
+
+impl PendingDeletions {
+    /// Location of the pending deletions file inside the data directory
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(ConfigManager::data_dir()?.join("pending_deletions.json"))
+    }
+
+    /// Load pending deletions, returning an empty set if there are none
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    /// Save pending deletions, removing the file once nothing is held
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if self.deletions.is_empty() {
+            if path.exists() {
+                std::fs::remove_file(path)
+                    .map_err(|e| anyhow::anyhow!("Failed to remove {}: {}", path.display(), e))?;
+            }
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize pending deletions: {}", e))?;
+        std::fs::write(path, json)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Add deletions to the held set, skipping duplicates
+    pub fn hold(&mut self, deletions: impl IntoIterator<Item = HeldDeletion>) {
+        for deletion in deletions {
+            if !self.deletions.contains(&deletion) {
+                self.deletions.push(deletion);
+            }
+        }
+        self.held_since.get_or_insert_with(Utc::now);
+        self.decision = None;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deletions.is_empty()
+    }
+}
+
+/// Decides whether a cycle's deletions are too large to apply unattended
+pub struct DeletionBrake<'a> {
+    limits: &'a DeletionSafety,
+}
+
+impl<'a> DeletionBrake<'a> {
+    pub fn new(limits: &'a DeletionSafety) -> Self {
+        Self { limits }
+    }
+
+    /// Number of tracked files removed by the given deletions, counting folder contents
+    pub fn affected_files(index: &SyncIndex, deletions: &[HeldDeletion]) -> usize {
+        deletions.iter()
+            .map(|deletion| {
+                index.entries_within(&deletion.remote)
+                    .filter(|entry| !entry.is_dir)
+                    .count()
+                    .max(1)
+            })
+            .sum()
+    }
+
+    /// Whether deleting `affected` of `tracked` files exceeds the configured limits
+    pub fn trips(&self, affected: usize, tracked: usize) -> bool {
+        if affected > self.limits.max_files {
+            return true;
+        }
+        affected >= PERCENT_RULE_MIN_FILES
+            && tracked > 0
+            && (affected as f64 / tracked as f64) * 100.0 > self.limits.max_percent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_brake_limits() {
+        let limits = DeletionSafety { max_files: 100, max_percent: 25.0 };
+        let brake = DeletionBrake::new(&limits);
+        assert!(!brake.trips(5, 6), "small deletions never trip the percentage rule");
+        assert!(brake.trips(30, 100));
+        assert!(!brake.trips(20, 100));
+        assert!(brake.trips(101, 100_000));
+    }
+
+    #[test]
+    fn test_pending_round_trip_and_cleanup() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("pending.json");
+        let mut pending = PendingDeletions::default();
+        let deletion = HeldDeletion { side: DeletionSide::Remote, remote: "/a.txt".to_string() };
+        pending.hold(vec![deletion.clone(), deletion]);
+        pending.save(&path).unwrap();
+
+        let mut loaded = PendingDeletions::load(&path).unwrap();
+        assert_eq!(loaded.deletions.len(), 1);
+        assert!(loaded.held_since.is_some());
+
+        loaded.deletions.clear();
+        loaded.save(&path).unwrap();
+        assert!(!path.exists());
+    }
+}
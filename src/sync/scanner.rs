@@ -0,0 +1,208 @@
+use crate::config::manager::SymlinkPolicy;
+use super::ignore_rules::IgnoreRules;
+use super::index::LocalIdentity;
+use jwalk::{Parallelism, WalkDirGeneric};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// What kind of local entry was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    /// A link kept as a link, under [`SymlinkPolicy::Store`]
+    Symlink,
+}
+
+/// One local entry as seen by the scanner
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanEntry {
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub mtime: i64,
+    pub inode: u64,
+    pub device: u64,
+    pub mode: u32,
+    /// Reached through a followed link; the other fields describe its target
+    pub followed: bool,
+}
+
+impl ScanEntry {
+    /// Identity of the entry as [`LocalIdentity::of`] reads it, without a
+    /// second `stat` unless a link was followed
+    pub fn identity(&self) -> Option<LocalIdentity> {
+        if self.followed {
+            return LocalIdentity::of(&self.path);
+        }
+        Some(LocalIdentity { device: self.device, inode: self.inode, size: self.size, mtime: self.mtime })
+    }
+
+    fn stat(path: PathBuf, follow: bool) -> Option<Self> {
+        let metadata = if follow { std::fs::metadata(&path) } else { std::fs::symlink_metadata(&path) }.ok()?;
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_dir() {
+            EntryKind::Dir
+        } else if file_type.is_symlink() {
+            EntryKind::Symlink
+        } else if file_type.is_file() {
+            EntryKind::File
+        } else {
+            return None;
+        };
+        Some(Self {
+            path,
+            kind,
+            size: metadata.size(),
+            mtime: metadata.mtime(),
+            inode: metadata.ino(),
+            device: metadata.dev(),
+            mode: metadata.mode(),
+            followed: follow,
+        })
+    }
+}
+
+/// Walks the sync folder with parallel directory reads
+///
+/// Ignored paths are pruned while reading, so their contents are never
+/// listed, and entries are stat'ed once on the worker threads. Results come
+/// out in sorted path order.
+#[derive(Clone)]
+pub struct Scanner {
+    root: PathBuf,
+    ignore: Option<Arc<IgnoreRules>>,
+    symlinks: SymlinkPolicy,
+}
+
+impl Scanner {
+    pub fn new(root: &Path) -> Self {
+        Self { root: root.to_path_buf(), ignore: None, symlinks: SymlinkPolicy::default() }
+    }
+
+    /// Leave out paths matched by ignore rules
+    pub fn with_ignore(mut self, rules: Arc<IgnoreRules>) -> Self {
+        self.ignore = Some(rules);
+        self
+    }
+
+    /// Treat symbolic links according to `policy`
+    pub fn with_symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Stream `subtree` and everything below it in sorted path order
+    pub fn scan(&self, subtree: &Path) -> impl Iterator<Item = ScanEntry> {
+        let scanner = self.clone();
+        let follow = self.symlinks == SymlinkPolicy::Follow;
+        let walker = WalkDirGeneric::<((), Option<ScanEntry>)>::new(subtree)
+            .sort(true)
+            .skip_hidden(false)
+            .follow_links(follow)
+            .parallelism(Parallelism::RayonDefaultPool { busy_timeout: std::time::Duration::from_secs(60) })
+            .process_read_dir(move |_, _, _, children| {
+                children.retain_mut(|child| {
+                    let Ok(child) = child else { return true };
+                    let path = child.parent_path.join(&child.file_name);
+                    // Known from the directory listing, without another stat
+                    let is_link = child.path_is_symlink();
+                    if is_link && !admits_symlink(&scanner.root, &path, scanner.symlinks) {
+                        return false;
+                    }
+                    // The parent was checked the same way, so only the child's own attribute is read
+                    let is_dir = child.file_type.is_dir();
+                    if scanner.ignore.as_ref().is_some_and(|rules| rules.is_ignored_entry(&path, is_dir)) {
+                        return false;
+                    }
+                    child.client_state = ScanEntry::stat(path, follow && is_link);
+                    child.client_state.is_some()
+                });
+            });
+
+        let root_entry = ScanEntry::stat(subtree.to_path_buf(), true);
+        walker.into_iter().filter_map(move |entry| match entry {
+            Ok(entry) if entry.depth == 0 => root_entry.clone(),
+            Ok(mut entry) => entry.client_state.take(),
+            Err(e) if e.loop_ancestor().is_some() => {
+                warn!("Not following symlink loop at {}", e.path().map_or_else(|| "?".into(), |p| p.display().to_string()));
+                None
+            }
+            Err(e) => {
+                debug!("Skipping unreadable entry: {}", e);
+                None
+            }
+        })
+    }
+
+    /// Sorted snapshot of the given subtrees, reading each directory once
+    ///
+    /// Used for incremental rescans of the folders the watcher flagged;
+    /// subtrees nested in another listed subtree are covered by it.
+    pub fn snapshot(&self, subtrees: &[PathBuf]) -> Vec<ScanEntry> {
+        let mut tops: Vec<&PathBuf> = subtrees.iter().collect();
+        tops.sort();
+        tops.dedup_by(|nested, top| nested.starts_with(top.as_path()));
+
+        let mut entries: Vec<ScanEntry> = tops.into_iter().flat_map(|top| self.scan(top)).collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        entries
+    }
+}
+
+/// Whether a symbolic link takes part in the sync under `policy`
+///
+/// When following links, those pointing outside the sync folder or
+/// nowhere are left out.
+pub fn admits_symlink(root: &Path, path: &Path, policy: SymlinkPolicy) -> bool {
+    match policy {
+        SymlinkPolicy::Skip => false,
+        SymlinkPolicy::Store => true,
+        SymlinkPolicy::Follow => match (path.canonicalize(), root.canonicalize()) {
+            (Ok(target), Ok(root)) if target.starts_with(&root) => true,
+            (Ok(target), _) => {
+                warn!("Not following {}: it points outside the sync folder to {}", path.display(), target.display());
+                false
+            }
+            _ => {
+                debug!("Not following dangling link {}", path.display());
+                false
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sorted_snapshot_skips_ignored_subtrees() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        for folder in ["b/node_modules/pkg", "a/deep", "c"] {
+            std::fs::create_dir_all(root.join(folder)).unwrap();
+        }
+        for file in ["b/node_modules/pkg/index.js", "a/deep/x.txt", "a/.hidden", "b/z.txt", "c/y.txt"] {
+            std::fs::write(root.join(file), b"data").unwrap();
+        }
+        let rules = IgnoreRules::load(root, &["node_modules/".to_string()], &[]).unwrap();
+        let scanner = Scanner::new(root).with_ignore(Arc::new(rules));
+
+        let paths: Vec<PathBuf> = scanner.scan(root)
+            .map(|entry| entry.path.strip_prefix(root).unwrap().to_path_buf())
+            .collect();
+        let expected: Vec<PathBuf> = ["", "a", "a/.hidden", "a/deep", "a/deep/x.txt", "b", "b/z.txt", "c", "c/y.txt"]
+            .iter().map(PathBuf::from).collect();
+        assert_eq!(paths, expected);
+
+        let partial = scanner.snapshot(&[root.join("c"), root.join("a/deep"), root.join("a")]);
+        assert_eq!(partial.iter().map(|entry| entry.path.clone()).collect::<Vec<_>>(),
+                   vec![root.join("a"), root.join("a/.hidden"), root.join("a/deep"), root.join("a/deep/x.txt"), root.join("c"), root.join("c/y.txt")]);
+        assert_eq!(partial[1].kind, EntryKind::File);
+        assert_eq!(partial[1].size, 4);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{ConfigManager, Result};
+use super::atomic;
+use super::paths;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Dropbox folders this machine does not sync
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelectiveSync {
+    /// Excluded folders as index keys
+    excluded: Vec<String>,
+}
+
+impl SelectiveSync {
+    /// Build the filter from configured folder paths such as `Photos` or `/Team/Archive/`
+    pub fn new<S: AsRef<str>>(folders: &[S]) -> Self {
+        let mut excluded: Vec<String> = folders.iter()
+            .filter_map(|folder| normalize(folder.as_ref()))
+            .map(|folder| paths::index_key(&folder))
+            .collect();
+        excluded.sort();
+        excluded.dedup();
+        Self { excluded }
+    }
+
+    /// Whether a Dropbox path lies in an excluded folder
+    pub fn is_excluded(&self, remote: &str) -> bool {
+        let key = paths::index_key(remote);
+        self.excluded.iter().any(|folder| paths::is_within(&key, folder))
+    }
+
+    /// Excluded folders as index keys
+    pub fn excluded(&self) -> &[String] {
+        &self.excluded
+    }
+}
+
+/// Turn a user-supplied folder into a Dropbox path, returning `None` for the root
+pub fn normalize(folder: &str) -> Option<String> {
+    let parts: Vec<&str> = folder.split('/').filter(|part| !part.is_empty()).collect();
+    if parts.is_empty() {
+        return None;
+    }
+    Some(format!("/{}", parts.join("/")))
+}
+
+/// What the daemon last applied, shared between the daemon and the CLI
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SelectiveState {
+    /// Excluded folders the daemon has already acted on, as index keys
+    pub applied: Vec<String>,
+    /// Newly excluded folders whose local copies wait for confirmation before removal
+    pub pending_removal: Vec<String>,
+    /// Set by the CLI to let the daemon remove the pending local copies
+    pub confirmed: bool,
+}
+
+impl SelectiveState {
+    /// Location of the selective sync state inside the data directory
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(ConfigManager::data_dir()?.join("selective_sync.json"))
+    }
+
+    /// Load the state, returning an empty one if none was saved
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize selective sync state: {}", e))?;
+        atomic::write_file(path, json.as_bytes())
+    }
+
+    /// Previously excluded folders that `current` no longer excludes
+    pub fn included_since(&self, current: &SelectiveSync) -> Vec<String> {
+        self.applied.iter()
+            .filter(|folder| !current.is_excluded(folder))
+            .cloned()
+            .collect()
+    }
+
+    /// Folders `current` excludes that were synced when the state was saved
+    pub fn excluded_since(&self, current: &SelectiveSync) -> Vec<String> {
+        let previous = SelectiveSync { excluded: self.applied.clone() };
+        current.excluded().iter()
+            .filter(|folder| !previous.is_excluded(folder))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exclusion_matches_whole_components() {
+        let selective = SelectiveSync::new(&["Team/Archive/", "/photos"]);
+        assert!(selective.is_excluded("/Team/Archive"));
+        assert!(selective.is_excluded("/team/archive/2019/report.pdf"));
+        assert!(selective.is_excluded("/Photos/cat.jpg"));
+        assert!(!selective.is_excluded("/Team/Archived.txt"));
+        assert!(!selective.is_excluded("/Team"));
+        assert!(SelectiveSync::new(&["/", ""]).excluded().is_empty());
+    }
+
+    #[test]
+    fn test_changes_since_last_applied() {
+        let state = SelectiveState {
+            applied: vec!["/old".to_string(), "/team/archive".to_string()],
+            ..SelectiveState::default()
+        };
+        let current = SelectiveSync::new(&["/Team", "/Videos"]);
+        assert_eq!(state.included_since(&current), vec!["/old".to_string()]);
+        assert_eq!(state.excluded_since(&current), vec!["/team".to_string(), "/videos".to_string()]);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::Result;
+use notify::event::{EventKind, ModifyKind, RenameMode};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// A change observed in the local sync folder
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LocalChange {
+    Created(PathBuf),
+    Modified(PathBuf),
+    Removed(PathBuf),
+    /// A rename whose both halves were seen, paired by inotify cookie
+    Moved { from: PathBuf, to: PathBuf },
+}
+
+impl LocalChange {
+    /// Path the change ended up at
+    pub fn path(&self) -> &Path {
+        match self {
+            LocalChange::Created(path) | LocalChange::Modified(path) | LocalChange::Removed(path) => path,
+            LocalChange::Moved { to, .. } => to,
+        }
+    }
+
+    /// The same change with every path passed through `f`
+    pub fn map_paths(self, f: impl Fn(&Path) -> PathBuf) -> Self {
+        match self {
+            LocalChange::Created(path) => LocalChange::Created(f(&path)),
+            LocalChange::Modified(path) => LocalChange::Modified(f(&path)),
+            LocalChange::Removed(path) => LocalChange::Removed(f(&path)),
+            LocalChange::Moved { from, to } => LocalChange::Moved { from: f(&from), to: f(&to) },
+        }
+    }
+}
+
+/// Recursive file system watcher over the sync folder
+pub struct LocalWatcher {
+    _watcher: RecommendedWatcher,
+    events: mpsc::UnboundedReceiver<notify::Result<Event>>,
+}
+
+impl LocalWatcher {
+    /// Start watching `root` recursively
+    pub fn new(root: &Path) -> Result<Self> {
+        let (tx, events) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })
+        .map_err(|e| anyhow::anyhow!("Failed to create file watcher: {}", e))?;
+
+        watcher.watch(root, RecursiveMode::Recursive)
+            .map_err(|e| anyhow::anyhow!("Failed to watch {}: {}", root.display(), e))?;
+
+        debug!("Watching {} for changes", root.display());
+        Ok(Self { _watcher: watcher, events })
+    }
+
+    /// Wait for the next burst of events and return them as coalesced changes
+    ///
+    /// Events are collected until none arrive for `settle`, so both halves of a
+    /// rename end up in the same batch. Returns `None` once the watcher stops.
+    pub async fn next_changes(&mut self, settle: Duration) -> Option<Vec<LocalChange>> {
+        let mut batch = Vec::new();
+        let first = self.events.recv().await?;
+        Self::push_event(&mut batch, first);
+
+        while let Ok(Some(event)) = tokio::time::timeout(settle, self.events.recv()).await {
+            Self::push_event(&mut batch, event);
+        }
+
+        Some(coalesce(batch))
+    }
+
+    fn push_event(batch: &mut Vec<Event>, event: notify::Result<Event>) {
+        match event {
+            Ok(event) => batch.push(event),
+            Err(e) => warn!("File watcher error: {}", e),
+        }
+    }
+}
+
+/// Translate raw watcher events into local changes
+///
+/// `MOVED_FROM`/`MOVED_TO` halves sharing an inotify cookie become a single
+/// [`LocalChange::Moved`]. An unpaired `MOVED_FROM` means the entry left the
+/// sync folder and is reported as removed; an unpaired `MOVED_TO` means it
+/// arrived from outside and is reported as created.
+pub fn coalesce(events: Vec<Event>) -> Vec<LocalChange> {
+    let mut both = HashSet::new();
+    let mut from_halves = HashMap::new();
+    let mut to_halves = HashSet::new();
+    for event in &events {
+        if let (EventKind::Modify(ModifyKind::Name(mode)), Some(cookie)) = (&event.kind, event.tracker()) {
+            match mode {
+                RenameMode::Both => {
+                    both.insert(cookie);
+                }
+                RenameMode::From => {
+                    if let Some(path) = event.paths.first() {
+                        from_halves.insert(cookie, path.clone());
+                    }
+                }
+                RenameMode::To => {
+                    to_halves.insert(cookie);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut changes = Vec::new();
+    let mut emitted = HashSet::new();
+    let mut created = HashSet::new();
+    for event in events {
+        let Some(path) = event.paths.first().cloned() else { continue };
+        let change = match event.kind {
+            EventKind::Create(_) => {
+                created.insert(path.clone());
+                LocalChange::Created(path)
+            }
+            EventKind::Remove(_) => LocalChange::Removed(path),
+            EventKind::Modify(ModifyKind::Name(mode)) => {
+                let cookie = event.tracker();
+                match (mode, cookie) {
+                    (RenameMode::Both, Some(cookie)) if event.paths.len() == 2 => {
+                        if !emitted.insert(cookie) {
+                            continue;
+                        }
+                        LocalChange::Moved { from: path, to: event.paths[1].clone() }
+                    }
+                    (RenameMode::From, Some(cookie)) => {
+                        if both.contains(&cookie) || to_halves.contains(&cookie) {
+                            continue;
+                        }
+                        LocalChange::Removed(path)
+                    }
+                    (RenameMode::To, Some(cookie)) => {
+                        if both.contains(&cookie) {
+                            continue;
+                        }
+                        match from_halves.get(&cookie) {
+                            Some(from) => {
+                                if !emitted.insert(cookie) {
+                                    continue;
+                                }
+                                LocalChange::Moved { from: from.clone(), to: path }
+                            }
+                            None => LocalChange::Created(path),
+                        }
+                    }
+                    _ => {
+                        if path.exists() {
+                            LocalChange::Created(path)
+                        } else {
+                            LocalChange::Removed(path)
+                        }
+                    }
+                }
+            }
+            EventKind::Modify(ModifyKind::Data(_))
+            | EventKind::Modify(ModifyKind::Metadata(_))
+            | EventKind::Modify(ModifyKind::Any) => {
+                if created.contains(&path) {
+                    continue;
+                }
+                LocalChange::Modified(path)
+            }
+            _ => continue,
+        };
+
+        if matches!(change, LocalChange::Modified(_)) && changes.contains(&change) {
+            continue;
+        }
+        changes.push(change);
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange};
+
+    fn rename(mode: RenameMode, cookie: usize, paths: &[&str]) -> Event {
+        let mut event = Event::new(EventKind::Modify(ModifyKind::Name(mode))).set_tracker(cookie);
+        for path in paths {
+            event = event.add_path(PathBuf::from(path));
+        }
+        event
+    }
+
+    #[test]
+    fn test_cookie_pairs_become_a_move() {
+        let events = vec![
+            rename(RenameMode::From, 7, &["/sync/a.txt"]),
+            rename(RenameMode::To, 7, &["/sync/b.txt"]),
+            rename(RenameMode::Both, 7, &["/sync/a.txt", "/sync/b.txt"]),
+        ];
+        assert_eq!(coalesce(events), vec![LocalChange::Moved {
+            from: PathBuf::from("/sync/a.txt"),
+            to: PathBuf::from("/sync/b.txt"),
+        }]);
+    }
+
+    #[test]
+    fn test_unpaired_halves_and_modifications() {
+        let events = vec![
+            rename(RenameMode::From, 1, &["/sync/gone.txt"]),
+            rename(RenameMode::To, 2, &["/sync/arrived.txt"]),
+            Event::new(EventKind::Create(CreateKind::File)).add_path(PathBuf::from("/sync/new.txt")),
+            Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Any))).add_path(PathBuf::from("/sync/new.txt")),
+            Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Any))).add_path(PathBuf::from("/sync/old.txt")),
+            Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Any))).add_path(PathBuf::from("/sync/old.txt")),
+        ];
+        assert_eq!(coalesce(events), vec![
+            LocalChange::Removed(PathBuf::from("/sync/gone.txt")),
+            LocalChange::Created(PathBuf::from("/sync/arrived.txt")),
+            LocalChange::Created(PathBuf::from("/sync/new.txt")),
+            LocalChange::Modified(PathBuf::from("/sync/old.txt")),
+        ]);
+    }
+}
@@ -1,10 +1,42 @@
-// TODO: Implement notifications in future task
-pub struct Notifications {
-    // TODO: Add notifications implementation
+use std::process::Command;
+use tracing::{debug, warn};
+
+/// Urgency of a desktop notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Normal,
+    Critical,
 }
 
+/// Desktop notifications through the freedesktop.org `notify-send` helper
+///
+/// When no notification daemon is available the message is still written to
+/// the log, so nothing important is lost on headless machines.
+#[derive(Default)]
+pub struct Notifications {}
+
 impl Notifications {
     pub fn new() -> Self {
         Self {}
     }
-} 
\ No newline at end of file
+
+    /// Show a notification with a short summary and a longer body
+    pub fn notify(&self, urgency: Urgency, summary: &str, body: &str) {
+        let level = match urgency {
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        };
+
+        let shown = Command::new("notify-send")
+            .args(["--app-name", "BoxDrop", "--urgency", level, summary, body])
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false);
+
+        if shown {
+            debug!("Notification shown: {}", summary);
+        } else {
+            warn!("{}: {}", summary, body);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 // TODO: Implement progress display in future task
+#[derive(Default)]
 pub struct Progress {
     // TODO: Add progress implementation
 }
@@ -1,10 +1,154 @@
-// TODO: Implement TUI in future task
+use crate::Result;
+use crate::config::manager::ConflictPolicy;
+use crate::conflict::queue::ConflictQueue;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How often the view reads the queue again to pick up conflicts the daemon settled or added
+const REFRESH: Duration = Duration::from_secs(1);
+
+/// Lines above and below the list of conflicts
+const CHROME_LINES: usize = 3;
+
+const HELP: &str = "up/down select   b both   l local   r Dropbox   n newest   m merge   q quit";
+
+/// Full-screen view of the conflict queue
+///
+/// Lists the queued conflicts with their decision and records a choice for
+/// the selected one, which the daemon applies on its next sync cycle just
+/// like `conflicts resolve`.
 pub struct Tui {
-    // TODO: Add TUI implementation
+    path: PathBuf,
+    selected: usize,
 }
 
 impl Tui {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, selected: 0 }
+    }
+
+    /// Show the view until the user quits, restoring the terminal afterwards
+    pub fn run(&mut self) -> Result<()> {
+        let mut out = std::io::stdout();
+        terminal::enable_raw_mode().map_err(terminal_error)?;
+        let result = execute!(out, EnterAlternateScreen, Hide)
+            .map_err(terminal_error)
+            .and_then(|_| self.event_loop(&mut out));
+        let _ = execute!(out, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+        result
+    }
+
+    fn event_loop(&mut self, out: &mut impl Write) -> Result<()> {
+        loop {
+            let mut queue = ConflictQueue::load(&self.path)?;
+            self.selected = self.selected.min(queue.conflicts.len().saturating_sub(1));
+            self.draw(out, &queue)?;
+
+            if !event::poll(REFRESH).map_err(terminal_error)? {
+                continue;
+            }
+            let Event::Key(key) = event::read().map_err(terminal_error)? else { continue };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => self.selected += 1,
+                KeyCode::Char(c) => {
+                    let Some(policy) = key_policy(c) else { continue };
+                    let Some(remote) = queue.conflicts.get(self.selected).map(|queued| queued.conflict.remote.clone()) else {
+                        continue;
+                    };
+                    queue.decide(&remote, policy);
+                    queue.save(&self.path)?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn draw(&self, out: &mut impl Write, queue: &ConflictQueue) -> Result<()> {
+        let (width, height) = terminal::size().map_err(terminal_error)?;
+        queue!(out, Clear(ClearType::All)).map_err(terminal_error)?;
+        for (row, line) in render(queue, self.selected, usize::from(height)).into_iter().enumerate() {
+            let line: String = line.chars().take(usize::from(width)).collect();
+            queue!(out, MoveTo(0, row as u16), Print(line)).map_err(terminal_error)?;
+        }
+        out.flush().map_err(terminal_error)
+    }
+}
+
+/// Policy recorded by a key, matching the initials of `--keep`
+fn key_policy(key: char) -> Option<ConflictPolicy> {
+    match key {
+        'b' => Some(ConflictPolicy::KeepBoth),
+        'l' => Some(ConflictPolicy::LocalWins),
+        'r' => Some(ConflictPolicy::RemoteWins),
+        'n' => Some(ConflictPolicy::NewestWins),
+        'm' => Some(ConflictPolicy::Merge),
+        _ => None,
+    }
+}
+
+/// Lines of the view for a terminal `height` lines tall, scrolled to keep the selection visible
+fn render(queue: &ConflictQueue, selected: usize, height: usize) -> Vec<String> {
+    let mut lines = vec![format!("{} conflicts waiting for a decision", queue.conflicts.len()), String::new()];
+    let rows = height.saturating_sub(CHROME_LINES).max(1);
+    let first = selected.saturating_sub(rows - 1);
+    if queue.is_empty() {
+        lines.push("Nothing to decide.".to_string());
     }
-} 
\ No newline at end of file
+    for (i, queued) in queue.conflicts.iter().enumerate().skip(first).take(rows) {
+        let decision = queued.decision
+            .map(|policy| format!("{:?} on the next sync cycle", policy))
+            .unwrap_or_else(|| "undecided".to_string());
+        lines.push(format!("{} {}  {}  {}",
+                           if i == selected { '>' } else { ' ' },
+                           queued.detected_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                           queued.conflict.remote, decision));
+    }
+    lines.push(HELP.to_string());
+    lines
+}
+
+fn terminal_error(e: std::io::Error) -> anyhow::Error {
+    anyhow::anyhow!("Failed to draw the conflict view: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conflict::resolver::Conflict;
+
+    #[test]
+    fn test_render_scrolls_to_the_selection() {
+        let mut queue = ConflictQueue::default();
+        for n in 0..10 {
+            let conflict = Conflict {
+                local: PathBuf::from(format!("/sync/{}.txt", n)),
+                remote: format!("/{}.txt", n),
+                base_rev: None,
+                local_size: 1,
+                local_modified: None,
+                remote_metadata: None,
+            };
+            queue.push(conflict, Vec::new());
+        }
+        queue.decide("/7.txt", ConflictPolicy::LocalWins);
+
+        let lines = render(&queue, 7, 8);
+        assert_eq!(lines.len(), 8);
+        assert!(lines[2].contains("/3.txt"));
+        assert!(lines[6].starts_with('>') && lines[6].ends_with("/7.txt  LocalWins on the next sync cycle"));
+        assert_eq!(lines[7], HELP);
+    }
+}
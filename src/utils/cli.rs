@@ -1,10 +1,526 @@
-// TODO: Implement CLI utilities in future task
+use crate::{ConfigManager, Result};
+use crate::config::manager::ConflictPolicy;
+use crate::conflict::backup::{Backup, BackupSource};
+use crate::conflict::details::ConflictDetails;
+use crate::conflict::queue::{ConflictFilter, ConflictQueue, ConflictReport, QueuedConflict};
+use crate::conflict::resolver::ConflictResolver;
+use crate::sync::ignore_rules::{self, IgnoreRules};
+use crate::sync::index::SyncIndex;
+use crate::sync::names::UnsyncableLog;
+use crate::sync::offline::OfflineQueue;
+use crate::sync::paths;
+use crate::sync::safety::{Decision, DeletionSide, HeldDeletion, PendingDeletions};
+use crate::sync::selective::{self, SelectiveState, SelectiveSync};
+use crate::ui::tui::Tui;
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Command-line interface of the sync daemon
+#[derive(Debug, Parser)]
+#[command(name = "boxdrop-sync-daemon", version, about)]
 pub struct Cli {
-    // TODO: Add CLI implementation
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the sync daemon in the foreground (default)
+    Run,
+    /// Show what the daemon is tracking and what needs attention
+    Status,
+    /// Review deletions held back by the mass-delete safety brake
+    Deletions {
+        #[command(subcommand)]
+        action: DeletionsCommand,
+    },
+    /// Review conflicts waiting for a decision and settle them
+    Conflicts {
+        #[command(subcommand)]
+        action: ConflictsCommand,
+    },
+    /// Inspect and restore files removed because they were deleted on Dropbox
+    Trash {
+        #[command(subcommand)]
+        action: TrashCommand,
+    },
+    /// Choose which Dropbox folders are synced to this machine
+    Selective {
+        #[command(subcommand)]
+        action: SelectiveCommand,
+    },
+    /// Inspect which local paths are ignored
+    Ignore {
+        #[command(subcommand)]
+        action: IgnoreCommand,
+    },
+}
+
+/// Print a summary of the sync state shared by the daemon
+pub fn show_status() -> Result<()> {
+    let config = ConfigManager::load()?;
+    let index = SyncIndex::load(&SyncIndex::default_path()?)?;
+    let pending = PendingDeletions::load(&PendingDeletions::default_path()?)?;
+    let selective = SelectiveState::load(&SelectiveState::default_path()?)?;
+    let unsyncable = UnsyncableLog::load(&UnsyncableLog::default_path()?)?;
+    let offline = OfflineQueue::load(&OfflineQueue::default_path()?)?;
+    let conflicts = ConflictQueue::load(&ConflictQueue::default_path()?)?;
+
+    println!("Sync folder:\t{}", config.sync_folder.display());
+    match offline.offline_since {
+        Some(since) => println!("Connection:\toffline since {} ({} changes pending)",
+                                since.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                                offline.pending_paths()),
+        None if offline.pending_paths() > 0 => println!("Connection:\tonline ({} changes waiting to be retried)",
+                                                        offline.pending_paths()),
+        None => println!("Connection:\tonline"),
+    }
+    println!("Tracked:\t{} files, {} folders",
+             index.entries().filter(|entry| !entry.is_dir).count(),
+             index.entries().filter(|entry| entry.is_dir).count());
+    if index.cursor().is_none() {
+        println!("Initial sync has not completed yet.");
+    }
+    if !pending.is_empty() {
+        println!("Held deletions:\t{} (see `deletions list`)", pending.deletions.len());
+    }
+    if !conflicts.is_empty() {
+        println!("Conflicts:\t{} awaiting a decision (see `conflicts list`)", conflicts.conflicts.len());
+    }
+    if !selective.pending_removal.is_empty() {
+        println!("Excluded folders awaiting removal:\t{} (see `selective list`)", selective.pending_removal.len());
+    }
+    if !unsyncable.entries.is_empty() {
+        println!("Unsyncable files:");
+        for (path, reason) in &unsyncable.entries {
+            println!("  {}\t{}", path.display(), reason);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DeletionsCommand {
+    /// List the held deletions
+    List,
+    /// Let the daemon propagate the held deletions
+    Approve,
+    /// Keep the files and let the daemon restore the missing copies
+    Reject,
+}
+
+impl DeletionsCommand {
+    pub fn execute(&self) -> Result<()> {
+        let path = PendingDeletions::default_path()?;
+        let mut pending = PendingDeletions::load(&path)?;
+        if pending.is_empty() {
+            println!("No deletions are waiting for confirmation.");
+            return Ok(());
+        }
+
+        match self {
+            DeletionsCommand::List => {
+                if let Some(since) = pending.held_since {
+                    println!("Deletions held since {}:", since.format("%Y-%m-%d %H:%M:%S UTC"));
+                }
+                for deletion in &pending.deletions {
+                    println!("  {:?}\t{}", deletion.side, deletion.remote);
+                }
+                match pending.decision {
+                    Some(decision) => println!("Decision {:?} will be applied on the next sync cycle.", decision),
+                    None => println!("Run `deletions approve` or `deletions reject` to continue."),
+                }
+            }
+            DeletionsCommand::Approve | DeletionsCommand::Reject => {
+                let decision = if matches!(self, DeletionsCommand::Approve) {
+                    Decision::Approve
+                } else {
+                    Decision::Reject
+                };
+                pending.decision = Some(decision);
+                pending.save(&path)?;
+                println!("{} deletions marked {:?}; the daemon applies this on its next sync cycle.",
+                         pending.deletions.len(), decision);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConflictsCommand {
+    /// List the conflicts waiting for a decision
+    List,
+    /// Compare both versions of a conflicting file, with a diff for text files
+    Show {
+        /// Dropbox path of the file, e.g. /Notes/todo.md
+        path: String,
+    },
+    /// Choose which version of a conflicting file to keep
+    Resolve {
+        /// Dropbox path of the file, e.g. /Notes/todo.md
+        path: String,
+        /// Version to keep
+        #[arg(long, value_enum)]
+        keep: Keep,
+    },
+    /// Choose one version for every conflict under a folder or matching a pattern
+    ResolveAll {
+        /// Only conflicts at or below this Dropbox folder, e.g. /Photos
+        #[arg(long)]
+        under: Option<String>,
+        /// Only conflicts matching this gitignore-style pattern, e.g. *.log
+        #[arg(long)]
+        matching: Option<String>,
+        /// Version to keep
+        #[arg(long, value_enum)]
+        keep: Keep,
+        /// Show what would be decided without recording anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show how the daemon settled decided conflicts
+    Report,
+    /// Browse the queue and decide conflicts in a full-screen view
+    Browse,
+}
+
+/// Version to keep when settling a conflict by hand
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Keep {
+    /// Both, saving the local edit as a conflicted copy
+    Both,
+    Local,
+    Remote,
+    /// Whichever was modified last
+    Newest,
+    /// Both edits merged line by line, or both files if the edits overlap
+    Merge,
+}
+
+impl From<Keep> for ConflictPolicy {
+    fn from(keep: Keep) -> Self {
+        match keep {
+            Keep::Both => ConflictPolicy::KeepBoth,
+            Keep::Local => ConflictPolicy::LocalWins,
+            Keep::Remote => ConflictPolicy::RemoteWins,
+            Keep::Newest => ConflictPolicy::NewestWins,
+            Keep::Merge => ConflictPolicy::Merge,
+        }
+    }
+}
+
+impl ConflictsCommand {
+    pub fn execute(&self) -> Result<()> {
+        let path = ConflictQueue::default_path()?;
+        let mut queue = ConflictQueue::load(&path)?;
+
+        match self {
+            ConflictsCommand::List => {
+                if queue.is_empty() {
+                    println!("No conflicts are waiting for a decision.");
+                }
+                for queued in &queue.conflicts {
+                    let decision = queued.decision
+                        .map(|policy| format!("{:?} on the next sync cycle", policy))
+                        .unwrap_or_else(|| "undecided".to_string());
+                    println!("{}\t{}\t{}",
+                             queued.detected_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                             queued.conflict.remote, decision);
+                    for backup in &queued.backups {
+                        println!("  backup\t{}", backup.display());
+                    }
+                }
+            }
+            ConflictsCommand::Show { path: remote } => {
+                let queued = queue.get(remote)
+                    .ok_or_else(|| anyhow::anyhow!("No conflict is queued for {}", remote))?;
+                let details = conflict_details(queued)?;
+                println!("{}", details.remote);
+                if let Some(base_rev) = &details.base_rev {
+                    println!("Last synced at rev {}", base_rev);
+                }
+                println!();
+                print!("{}", details.table());
+                match &details.diff {
+                    Some(diff) if !diff.is_empty() => print!("\n{}", diff),
+                    Some(_) => println!("\nThe contents are identical."),
+                    None => {}
+                }
+            }
+            ConflictsCommand::Resolve { path: remote, keep } => {
+                if !queue.decide(remote, (*keep).into()) {
+                    return Err(anyhow::anyhow!("No conflict is queued for {}", remote));
+                }
+                queue.save(&path)?;
+                println!("Keeping {:?} for {}; the daemon applies this on its next sync cycle.", keep, remote);
+            }
+            ConflictsCommand::ResolveAll { under, matching, keep, dry_run } => {
+                let filter = ConflictFilter::new(under.as_deref(), matching.as_deref())?;
+                let policy = ConflictPolicy::from(*keep);
+                let mut effects: BTreeMap<String, usize> = BTreeMap::new();
+                for queued in queue.select(&filter) {
+                    let resolution = ConflictResolver::apply_policy(policy, &queued.conflict);
+                    *effects.entry(format!("{:?}", resolution)).or_default() += 1;
+                    println!("{:?}\t{}\t({} backups)", resolution, queued.conflict.remote, queued.backups.len());
+                }
+                if effects.is_empty() {
+                    println!("No queued conflict matches.");
+                    return Ok(());
+                }
+                let summary: Vec<String> = effects.iter().map(|(effect, count)| format!("{} {}", count, effect)).collect();
+                println!("Effect as of detection: {}", summary.join(", "));
+                if *dry_run {
+                    println!("Dry run; nothing was recorded.");
+                    return Ok(());
+                }
+                let decided = queue.decide_matching(&filter, policy);
+                queue.save(&path)?;
+                println!("Keeping {:?} for {} conflicts; the daemon applies this on its next sync cycle \
+                          and records the outcome in `conflicts report`.", keep, decided.len());
+                println!("Each conflict is settled on its own against the state of both sides at that time: \
+                          one that fails stays queued without a decision while the others still go ahead.");
+            }
+            ConflictsCommand::Browse => Tui::new(path).run()?,
+            ConflictsCommand::Report => {
+                let report = ConflictReport::load(&ConflictReport::default_path()?)?;
+                if report.settled.is_empty() {
+                    println!("No decided conflicts have been settled yet.");
+                }
+                for settled in &report.settled {
+                    let outcome = settled.error.as_ref()
+                        .map(|error| format!("failed: {}", error))
+                        .unwrap_or_else(|| settled.resolution.clone());
+                    println!("{}\t{}\t{:?}\t{}",
+                             settled.settled_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                             settled.remote, settled.decision, outcome);
+                }
+                let failed = report.settled.iter().filter(|settled| settled.error.is_some()).count();
+                if failed > 0 {
+                    println!("{} failed; those conflicts stay queued without a decision (see `conflicts list`).", failed);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Details of a queued conflict, compared using the backups taken when it was detected
+fn conflict_details(queued: &QueuedConflict) -> Result<ConflictDetails> {
+    let config = ConfigManager::load()?;
+    let backups = Backup::conflicts(&config).list()?;
+    let content = |source: BackupSource| backups.iter()
+        .filter(|item| queued.backups.contains(&item.stored) && item.info.source == Some(source))
+        .find_map(|item| std::fs::read(&item.stored).ok());
+
+    let local = content(BackupSource::Local).or_else(|| std::fs::read(&queued.conflict.local).ok());
+    let dropbox = content(BackupSource::Dropbox);
+    Ok(ConflictDetails::new(&queued.conflict, local.as_deref(), dropbox.as_deref()))
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TrashCommand {
+    /// List trashed files, oldest first
+    List,
+    /// Move a trashed file back into the sync folder
+    Restore {
+        /// Path of the file relative to the sync folder
+        path: PathBuf,
+        /// Day the file was trashed (YYYY-MM-DD); defaults to the newest copy
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+    /// Apply the retention period and size cap now
+    Purge,
+}
+
+impl TrashCommand {
+    pub fn execute(&self) -> Result<()> {
+        let config = ConfigManager::load()?;
+        let trash = Backup::trash(&config);
+
+        match self {
+            TrashCommand::List => {
+                let items = trash.list()?;
+                if items.is_empty() {
+                    println!("The trash is empty.");
+                }
+                for item in items {
+                    println!("{}\t{:>10}\t{}", item.date, item.size, item.relative.display());
+                }
+            }
+            TrashCommand::Restore { path, date } => {
+                let restored = trash.restore(path, *date, &config.sync_folder.join(path))?;
+                println!("Restored {}", restored.display());
+            }
+            TrashCommand::Purge => {
+                let removed = trash.prune()?;
+                println!("Removed {} expired files from the trash.", removed);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SelectiveCommand {
+    /// List excluded folders and local copies waiting for removal
+    List,
+    /// Stop syncing a Dropbox folder to this machine
+    Exclude {
+        /// Dropbox path of the folder, e.g. /Team/Archive
+        folder: String,
+    },
+    /// Sync a previously excluded folder again
+    Include {
+        /// Dropbox path of the folder, e.g. /Team/Archive
+        folder: String,
+    },
+    /// Let the daemon remove the local copies of newly excluded folders
+    Confirm,
 }
 
-impl Cli {
-    pub fn new() -> Self {
-        Self {}
+impl SelectiveCommand {
+    pub fn execute(&self) -> Result<()> {
+        let mut config = ConfigManager::load()?;
+        let state_path = SelectiveState::default_path()?;
+        let mut state = SelectiveState::load(&state_path)?;
+
+        match self {
+            SelectiveCommand::List => {
+                if config.selective_sync.excluded_folders.is_empty() {
+                    println!("All folders are synced.");
+                }
+                for folder in &config.selective_sync.excluded_folders {
+                    println!("excluded\t{}", folder);
+                }
+                for folder in &state.pending_removal {
+                    println!("pending removal\t{}", folder);
+                }
+            }
+            SelectiveCommand::Exclude { folder } => {
+                let folder = selective::normalize(folder)
+                    .ok_or_else(|| anyhow::anyhow!("The Dropbox root cannot be excluded"))?;
+                if SelectiveSync::new(&config.selective_sync.excluded_folders).is_excluded(&folder) {
+                    println!("{} is already excluded.", folder);
+                    return Ok(());
+                }
+                config.selective_sync.excluded_folders.push(folder.clone());
+                config.save()?;
+                println!("Excluded {}; local copies are removed after `selective confirm`.", folder);
+            }
+            SelectiveCommand::Include { folder } => {
+                let folder = selective::normalize(folder)
+                    .ok_or_else(|| anyhow::anyhow!("Give the Dropbox path of an excluded folder"))?;
+                let key = paths::index_key(&folder);
+                let excluded = &mut config.selective_sync.excluded_folders;
+                let before = excluded.len();
+                excluded.retain(|existing| {
+                    selective::normalize(existing).is_none_or(|existing| paths::index_key(&existing) != key)
+                });
+                if excluded.len() == before {
+                    println!("{} is not in the excluded list.", folder);
+                    return Ok(());
+                }
+                let still_excluded = SelectiveSync::new(excluded).is_excluded(&folder);
+                config.save()?;
+                if still_excluded {
+                    println!("Included {}, but a parent folder is still excluded.", folder);
+                } else {
+                    println!("Included {}; the daemon downloads it on its next sync cycle.", folder);
+                }
+            }
+            SelectiveCommand::Confirm => {
+                if state.pending_removal.is_empty() {
+                    println!("No local copies are waiting for removal.");
+                    return Ok(());
+                }
+                state.confirmed = true;
+                state.save(&state_path)?;
+                println!("{} folders will be removed locally on the next sync cycle.", state.pending_removal.len());
+            }
+        }
+        Ok(())
     }
-} 
\ No newline at end of file
+}
+
+#[derive(Debug, Subcommand)]
+pub enum IgnoreCommand {
+    /// Show which rule decides whether a path is synced
+    Explain {
+        /// Path inside the sync folder, absolute or relative to it
+        path: PathBuf,
+    },
+    /// Mark a path as ignored with the attribute the official Dropbox client uses
+    Set {
+        /// Path inside the sync folder, absolute or relative to it
+        path: PathBuf,
+    },
+    /// Remove the ignored attribute so the path is synced again
+    Clear {
+        /// Path inside the sync folder, absolute or relative to it
+        path: PathBuf,
+    },
+}
+
+impl IgnoreCommand {
+    pub fn execute(&self) -> Result<()> {
+        let config = ConfigManager::load()?;
+        let root = &config.sync_folder;
+
+        match self {
+            IgnoreCommand::Explain { path } => {
+                let path = resolve_local(root, path)?;
+                let rules = IgnoreRules::load(root, &config.ignore_patterns, &config.stores())?;
+                match rules.explain(&path, path.is_dir()) {
+                    Some(rule) if rule.whitelist => {
+                        println!("{} is synced: re-included by `{}` in {}", path.display(), rule.pattern, rule.source);
+                    }
+                    Some(rule) => {
+                        println!("{} is ignored by `{}` in {}", path.display(), rule.pattern, rule.source);
+                    }
+                    None => println!("{} is synced: no ignore rule matches", path.display()),
+                }
+            }
+            IgnoreCommand::Set { path } | IgnoreCommand::Clear { path } => {
+                let path = resolve_local(root, path)?;
+                let remote = paths::remote_path(root, &path)
+                    .ok_or_else(|| anyhow::anyhow!("{} cannot be mapped to Dropbox", path.display()))?;
+                let ignored = matches!(self, IgnoreCommand::Set { .. });
+                ignore_rules::set_ignored_xattr(&path, ignored)?;
+
+                let pending_path = PendingDeletions::default_path()?;
+                let mut pending = PendingDeletions::load(&pending_path)?;
+                let held = HeldDeletion { side: DeletionSide::Remote, remote };
+                if ignored {
+                    println!("{} is now ignored.", path.display());
+                    let index = SyncIndex::load(&SyncIndex::default_path()?)?;
+                    if index.get(&held.remote).is_some() || index.contains_within(&held.remote) {
+                        pending.hold([held]);
+                        pending.save(&pending_path)?;
+                        println!("Its Dropbox copy is kept until you run `deletions approve`.");
+                    }
+                } else {
+                    println!("{} is synced again.", path.display());
+                    if pending.deletions.contains(&held) {
+                        pending.deletions.retain(|deletion| deletion != &held);
+                        pending.save(&pending_path)?;
+                        println!("Dropped its held removal from Dropbox.");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Resolve a path given on the command line against the sync folder
+fn resolve_local(root: &Path, path: &Path) -> Result<PathBuf> {
+    let path = if path.is_absolute() { path.to_path_buf() } else { root.join(path) };
+    if !path.starts_with(root) {
+        return Err(anyhow::anyhow!("{} is outside the sync folder {}", path.display(), root.display()));
+    }
+    Ok(path)
+}
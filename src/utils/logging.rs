@@ -1,4 +1,5 @@
 // TODO: Implement logging utilities in future task
+#[derive(Default)]
 pub struct Logging {
     // TODO: Add logging implementation
 }
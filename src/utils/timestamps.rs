@@ -1,10 +1,46 @@
-// TODO: Implement timestamp utilities in future task
-pub struct Timestamps {
-    // TODO: Add timestamp implementation
+use crate::Result;
+use chrono::{DateTime, Utc};
+use std::fs::File;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Parse a Dropbox RFC 3339 timestamp such as `client_modified`
+pub fn parse_dropbox_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Set the last-modified time of a local file or directory
+pub fn set_mtime(path: &Path, modified: DateTime<Utc>) -> Result<()> {
+    let file = File::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open {} to set mtime: {}", path.display(), e))?;
+    file.set_modified(SystemTime::from(modified))
+        .map_err(|e| anyhow::anyhow!("Failed to set mtime on {}: {}", path.display(), e))
+}
+
+/// Apply a Dropbox `client_modified` value to a local path, if it parses
+pub fn apply_client_modified(path: &Path, client_modified: Option<&str>) -> Result<()> {
+    match client_modified.and_then(parse_dropbox_time) {
+        Some(modified) => set_mtime(path, modified),
+        None => Ok(()),
+    }
 }
 
-impl Timestamps {
-    pub fn new() -> Self {
-        Self {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_set_mtime_from_client_modified() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, b"hello").unwrap();
+
+        apply_client_modified(&path, Some("2015-05-12T15:50:38Z")).unwrap();
+
+        let modified: DateTime<Utc> = std::fs::metadata(&path).unwrap().modified().unwrap().into();
+        assert_eq!(modified, parse_dropbox_time("2015-05-12T15:50:38Z").unwrap());
     }
-} 
\ No newline at end of file
+}